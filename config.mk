@@ -7,6 +7,12 @@ MANPREFIX = $(PREFIX)/share/man
 # Xinerama, set to false/empty if you don't want it
 XINERAMA=true
 
+# Wayland (wlr-layer-shell) backend, set to true to build it, see --backend
+WAYLAND=false
+
+# Software backend for --render-to and `make check`, set to false/empty if you don't want it
+HEADLESS=true
+
 # flags (not used for dmenu)
 CFLAGS = -c -pedantic -std=c99 -Wall -Os -D_DEFAULT_SOURCE
 
@@ -0,0 +1,74 @@
+//! Matching against large synthetic item lists, as with `find / | dmenu`
+//! Run with `make bench`
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+#[allow(dead_code)]
+#[path = "../dmenu/matching.rs"]
+mod matching;
+
+use matching::{matcher, Search};
+
+const WORDS: [&str; 16] = ["usr", "share", "local", "lib", "bin", "src", "dmenu", "firefox",
+			   "config", "icons", "hicolor", "scalable", "apps", "main", "rs", "png"];
+
+// Paths like "lib/firefox/icons-1234.png", the same every run
+fn items(len: usize) -> Vec<String> {
+    let mut seed: u64 = 0x2545F4914F6CDD1D;
+    let mut next = move || {
+	seed ^= seed << 13;
+	seed ^= seed >> 7;
+	seed ^= seed << 17;
+	seed as usize
+    };
+    (0..len).map(|_| {
+	let depth = 2 + next() % 4;
+	let dirs: Vec<&str> = (0..depth).map(|_| WORDS[next() % WORDS.len()]).collect();
+	format!("{}-{}.{}", dirs.join("/"), next() % 10000, WORDS[next() % WORDS.len()])
+    }).collect()
+}
+
+// Searching everything from scratch, as on the first keystroke
+fn fresh(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fresh");
+    group.sample_size(10);
+    for len in [10_000, 500_000].iter() {
+	let items = items(*len);
+	for name in ["substring", "tokens", "fuzzy", "regex"].iter() {
+	    let mut matcher = matcher(name).unwrap();
+	    group.bench_with_input(BenchmarkId::new(*name, len), &items, |b, items| b.iter(|| {
+		Search::default().run(&mut *matcher, black_box("fire"), false, items.len(),
+				      |i| items[i].as_str())
+	    }));
+	}
+    }
+    group.finish();
+}
+
+// Typing "firefox png" one char at a time, reusing the previous search or not
+fn typing(c: &mut Criterion) {
+    let input = "firefox png";
+    let items = items(500_000);
+    let mut group = c.benchmark_group("typing");
+    group.sample_size(10);
+    for name in ["substring", "tokens", "fuzzy"].iter() {
+	let mut matcher = matcher(name).unwrap();
+	group.bench_function(BenchmarkId::new("narrowing", name), |b| b.iter(|| {
+	    let mut search = Search::default();
+	    for end in 1..=input.len() {
+		black_box(search.run(&mut *matcher, &input[..end], false, items.len(),
+				     |i| items[i].as_str()));
+	    }
+	}));
+	group.bench_function(BenchmarkId::new("from scratch", name), |b| b.iter(|| {
+	    for end in 1..=input.len() {
+		black_box(Search::default().run(&mut *matcher, &input[..end], false, items.len(),
+						|i| items[i].as_str()));
+	    }
+	}));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, fresh, typing);
+criterion_main!(benches);
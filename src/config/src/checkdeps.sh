@@ -46,6 +46,18 @@ if [ "$XINERAMA" = "true" ]; then
     fi
 fi
 
+if [ "$WAYLAND" = "true" ]; then
+    printf "Checking for xkbcommon headers... "
+    if echo "#include <xkbcommon/xkbcommon.h>" | $CC -x c -c - -o /dev/null;
+    then
+	echo "yes"
+    else
+	echo "no"
+	>&2 echo "Build-time dependency <xkbcommon/xkbcommon.h> is not present. Install libxkbcommon package(s) or disable Wayland in config.mk"
+	FAILED=1
+    fi
+fi
+
 if [ $FAILED != 0 ]; then
     exit 1
 fi
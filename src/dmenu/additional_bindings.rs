@@ -25,6 +25,13 @@ pub mod fontconfig {
     pub const FC_CHARSET:  *const i8 = main::FC_CHARSET.as_ptr()  as *const i8;
     pub const FC_COLOR:    *const i8 = main::FC_COLOR.as_ptr()    as *const i8;
     pub const FC_FAMILY:   *mut   i8 = main::FC_FAMILY.as_ptr()   as *mut   i8;
+    // for canvas.rs, which loads fonts itself
+    #[cfg(any(feature = "headless", feature = "wayland"))]
+    pub const FC_FILE:       *const i8 = main::FC_FILE.as_ptr()       as *const i8;
+    #[cfg(any(feature = "headless", feature = "wayland"))]
+    pub const FC_INDEX:      *const i8 = main::FC_INDEX.as_ptr()      as *const i8;
+    #[cfg(any(feature = "headless", feature = "wayland"))]
+    pub const FC_PIXEL_SIZE: *const i8 = main::FC_PIXEL_SIZE.as_ptr() as *const i8;
 }
 pub mod xlib {
     #![allow(non_upper_case_globals)]
@@ -33,3 +40,8 @@ pub mod xlib {
     use super::raw::xlib;
     pub use xlib::{XNInputStyle, XNClientWindow, XNFocusWindow};
 }
+pub mod xrender {
+    #![allow(non_upper_case_globals)]
+    use libc::c_int;
+    pub const PictStandardARGB32: c_int = 0; // not in the x11 crate
+}
@@ -0,0 +1,120 @@
+use libc::{c_int, c_uint};
+use std::any::Any;
+use std::fmt::Debug;
+
+use crate::config::Config;
+use crate::xorg::X11;
+#[cfg(feature = "headless")]
+use crate::headless::Headless;
+#[cfg(feature = "wayland")]
+use crate::wayland::Wayland;
+use crate::result::*;
+
+/// Backends --backend can choose from
+pub const BACKENDS: [&str; 2] = ["x11", "wayland"];
+
+/// An opaque color, resolved by Backend::color
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    /// Parses #rgb or #rrggbb
+    #[cfg(any(feature = "headless", feature = "wayland"))]
+    pub fn parse(name: &str) -> Option<Self> {
+	if !name.starts_with('#') {
+	    return None;
+	}
+	let hex = &name[1..];
+	let digit = |i: usize| u8::from_str_radix(hex.get(i..i+1)?, 16).ok();
+	let byte = |i: usize| u8::from_str_radix(hex.get(i..i+2)?, 16).ok();
+	match hex.len() {
+	    3 => Some(Self{r: digit(0)?*17, g: digit(1)?*17, b: digit(2)?*17}),
+	    6 => Some(Self{r: byte(0)?, g: byte(2)?, b: byte(4)?}),
+	    _ => None,
+	}
+    }
+}
+
+/// Something that happened in the window, in terms of X keysyms and modifier masks
+/// whatever the backend is, so that run.rs can handle them all the same way
+#[derive(Debug)]
+pub enum Event {
+    /// text is what the key types, if anything
+    Key{ksym: u32, text: String, state: c_uint},
+    /// Buttons are numbered like X, so 4 and 5 scroll
+    Button{button: c_uint, x: c_int, y: c_int, state: c_uint},
+    /// The selection was handed over, so it can be pasted
+    Paste,
+    /// The window is gone
+    Close,
+}
+
+/// Everything Drw needs from a windowing system: a window to draw into, fonts and colors
+/// to draw with, input, and the clipboard.
+/// Drawing happens off-screen, and shows up once mapped.
+pub trait Backend: Debug {
+    /// Loads fonts by fontconfig name, the first being the primary one
+    fn load_fonts(&mut self, names: &[String]) -> CompResult<()>;
+    /// Height of the primary font
+    fn font_height(&self) -> c_uint;
+    /// Height of the tallest font loaded so far, including fallbacks
+    fn max_font_height(&self) -> c_uint;
+    /// Resolves a color name, None if it isn't known
+    fn color(&mut self, name: &str) -> Option<Color>;
+    /// How wide text is when drawn, falling back to other fonts for missing chars
+    fn text_width(&mut self, text: &str) -> CompResult<c_uint>;
+    /// Draws text starting at x, vertically centered within y..y+h
+    fn text(&mut self, x: c_int, y: c_int, h: c_uint, text: &str, color: Color) -> CompResult<()>;
+    fn rect(&mut self, x: c_int, y: c_int, w: c_uint, h: c_uint, color: Color, filled: bool);
+    /// Takes size*size premultiplied ARGB pixels, returning an id for draw_image
+    fn load_image(&mut self, pixels: Vec<u32>, size: c_uint) -> usize;
+    fn draw_image(&mut self, id: usize, x: c_int, y: c_int);
+
+    /// Grabs the keyboard before the window is shown, so no keys get lost
+    fn grab_keyboard(&mut self, config: &Config) -> CompResult<()>;
+    /// Creates and shows the window, h pixels tall and filled with bg
+    /// Returns how wide it turned out to be
+    fn create_window(&mut self, h: c_int, bg: Color, config: &Config) -> CompResult<c_int>;
+    /// Shows what was drawn in the given area
+    fn map(&mut self, x: c_int, y: c_int, w: c_int, h: c_int);
+
+    /// Polled along with stdin and helpers, readable once there may be events
+    fn fd(&self) -> c_int;
+    /// Milliseconds until the backend wants to run without fd being readable, or -1
+    fn timeout(&self) -> c_int {
+	-1
+    }
+    /// Whether next_event would return without blocking
+    fn pending(&mut self) -> bool;
+    /// Waits for the next event. None if it was handled by the backend itself
+    fn next_event(&mut self) -> CompResult<Option<Event>>;
+    /// The clipboard's contents
+    fn clipboard(&mut self) -> CompResult<String>;
+
+    /// For things only a particular backend can do, such as --windows on X11
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Connects to the backend chosen with --backend, or none at all for --render-to
+pub fn open(config: &Config) -> CompResult<Box<dyn Backend>> {
+    if config.render_to.is_some() {
+	#[cfg(feature = "headless")]
+	return Ok(Box::new(Headless::open(config)?));
+	#[cfg(not(feature = "headless"))]
+	return Die::stderr("--render-to: dmenu was built without the headless backend, \
+			    see HEADLESS in config.mk".to_owned());
+    }
+    match config.backend.as_str() {
+	"x11" => Ok(Box::new(X11::open(config)?)),
+	#[cfg(feature = "wayland")]
+	"wayland" => Ok(Box::new(Wayland::open(config)?)),
+	#[cfg(not(feature = "wayland"))]
+	"wayland" => Die::stderr("--backend: dmenu was built without Wayland support, \
+				  see WAYLAND in config.mk".to_owned()),
+	name => Die::stderr(format!("--backend: unknown backend '{}'", name)),
+    }
+}
@@ -0,0 +1,227 @@
+use x11::xlib::{XStringToKeysym, ShiftMask, ControlMask, Mod1Mask};
+use x11::keysym::*;
+use libc::c_uint;
+use std::{collections::HashMap, ffi::CString};
+
+use crate::plugin;
+
+/// Something a key can be bound to, see --bind
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Copy the selected item to the input
+    Complete,
+    /// Print the selection, or the marked items, and exit
+    Accept,
+    /// Like Accept, but keep the menu open
+    AcceptContinue,
+    /// Print the input exactly as typed and exit
+    AcceptInput,
+    AcceptInputContinue,
+    /// Exit without printing anything, returning failure
+    Exit,
+    /// Select the first or last item
+    First,
+    Last,
+    PageUp,
+    PageDown,
+    /// Move the selection, or recall history when nothing matches
+    Up,
+    Down,
+    /// Move the cursor, or the selection when the cursor is at the end of a horizontal menu
+    Left,
+    Right,
+    Backspace,
+    Delete,
+    DeleteToLineStart,
+    DeleteToLineEnd,
+    DeleteWordLeft,
+    DeleteWordRight,
+    WordLeft,
+    WordRight,
+    /// Mark or unmark the selection, with --multi
+    Mark,
+    NextMode,
+    PrevMode,
+    Paste,
+    HistoryOlder,
+    HistoryNewer,
+    NextMatcher,
+    /// Leave insert mode for vi style editing, see --vi
+    NormalMode,
+    /// Handled by a runtime plugin, named PLUGIN.ACTION
+    Plugin(String),
+}
+use Action::*;
+
+// Names of the built-in actions, as used by --bind
+const NAMES: [(&str, Action); 30] = [
+    ("complete", Complete),
+    ("accept", Accept),
+    ("accept-continue", AcceptContinue),
+    ("accept-input", AcceptInput),
+    ("accept-input-continue", AcceptInputContinue),
+    ("exit", Exit),
+    ("first", First),
+    ("last", Last),
+    ("page-up", PageUp),
+    ("page-down", PageDown),
+    ("up", Up),
+    ("down", Down),
+    ("left", Left),
+    ("right", Right),
+    ("backspace", Backspace),
+    ("delete", Delete),
+    ("delete-to-line-start", DeleteToLineStart),
+    ("delete-to-line-end", DeleteToLineEnd),
+    ("delete-word-left", DeleteWordLeft),
+    ("delete-word-right", DeleteWordRight),
+    ("word-left", WordLeft),
+    ("word-right", WordRight),
+    ("mark", Mark),
+    ("next-mode", NextMode),
+    ("prev-mode", PrevMode),
+    ("paste", Paste),
+    ("history-older", HistoryOlder),
+    ("history-newer", HistoryNewer),
+    ("next-matcher", NextMatcher),
+    ("normal-mode", NormalMode),
+];
+
+impl Action {
+    /// Looks up a built-in action, or an action registered by a runtime plugin
+    pub fn parse(name: &str) -> Option<Self> {
+	if name.contains('.') {
+	    return plugin::has_action(name).then(|| Plugin(name.to_string()));
+	}
+	NAMES.iter().find(|(n, _)| *n == name).map(|(_, action)| action.clone())
+    }
+}
+
+/// Which action each key runs, keyed by keysym and modifier mask
+/// Keys without a binding type their text, unless Control or Alt is held.
+#[derive(Debug, Clone)]
+pub struct Bindings {
+    keys: HashMap<(u32, c_uint), Action>,
+}
+
+impl Bindings {
+    /// The default bindings, which are mostly those of dmenu
+    pub fn builtin() -> Self {
+	let mut keys = HashMap::new();
+	for (ksym, mods, action) in vec![
+	    (XK_Tab, 0, Complete),
+	    (XK_i, ControlMask, Complete),
+	    (XK_Return, 0, Accept),
+	    (XK_KP_Enter, 0, Accept),
+	    (XK_j, ControlMask, Accept),
+	    (XK_m, ControlMask, Accept),
+	    (XK_Return, ControlMask, AcceptContinue),
+	    (XK_KP_Enter, ControlMask, AcceptContinue),
+	    (XK_Return, ShiftMask, AcceptInput),
+	    (XK_KP_Enter, ShiftMask, AcceptInput),
+	    (XK_J, ControlMask, AcceptInput),
+	    (XK_M, ControlMask, AcceptInput),
+	    (XK_Return, ControlMask|ShiftMask, AcceptInputContinue),
+	    (XK_KP_Enter, ControlMask|ShiftMask, AcceptInputContinue),
+	    (XK_Escape, 0, Exit),
+	    (XK_c, ControlMask, Exit),
+	    (XK_g, ControlMask, Exit),
+	    (XK_bracketleft, ControlMask, Exit),
+	    (XK_Home, 0, First),
+	    (XK_a, ControlMask, First),
+	    (XK_g, Mod1Mask, First),
+	    (XK_End, 0, Last),
+	    (XK_e, ControlMask, Last),
+	    (XK_G, Mod1Mask, Last),
+	    (XK_Prior, 0, PageUp),
+	    (XK_k, Mod1Mask, PageUp),
+	    (XK_Next, 0, PageDown),
+	    (XK_j, Mod1Mask, PageDown),
+	    (XK_Up, 0, Up),
+	    (XK_p, ControlMask, Up),
+	    (XK_h, Mod1Mask, Up),
+	    (XK_Down, 0, Down),
+	    (XK_n, ControlMask, Down),
+	    (XK_l, Mod1Mask, Down),
+	    (XK_Left, 0, Left),
+	    (XK_b, ControlMask, Left),
+	    (XK_Right, 0, Right),
+	    (XK_f, ControlMask, Right),
+	    (XK_BackSpace, 0, Backspace),
+	    (XK_h, ControlMask, Backspace),
+	    (XK_Delete, 0, Delete),
+	    (XK_d, ControlMask, Delete),
+	    (XK_u, ControlMask, DeleteToLineStart),
+	    (XK_k, ControlMask, DeleteToLineEnd),
+	    (XK_w, ControlMask, DeleteWordLeft),
+	    (XK_BackSpace, ControlMask, DeleteWordLeft),
+	    (XK_Delete, ControlMask, DeleteWordRight),
+	    (XK_Left, ControlMask, WordLeft),
+	    (XK_b, Mod1Mask, WordLeft),
+	    (XK_Right, ControlMask, WordRight),
+	    (XK_f, Mod1Mask, WordRight),
+	    (XK_ISO_Left_Tab, 0, Mark),
+	    (XK_space, ControlMask, Mark),
+	    (XK_Tab, ControlMask, NextMode),
+	    (XK_ISO_Left_Tab, ControlMask, PrevMode),
+	    (XK_y, ControlMask, Paste),
+	    (XK_Y, ControlMask, Paste),
+	    (XK_p, Mod1Mask, HistoryOlder),
+	    (XK_n, Mod1Mask, HistoryNewer),
+	    (XK_m, Mod1Mask, NextMatcher),
+	] {
+	    keys.insert((ksym, mods), action);
+	}
+	Self{keys}
+    }
+
+    /// For --vi, Escape enters normal mode instead of exiting, unless it was rebound
+    pub fn vi(&mut self) {
+	if self.keys.get(&(XK_Escape, 0)) == Some(&Exit) {
+	    self.keys.insert((XK_Escape, 0), NormalMode);
+	}
+    }
+
+    /// The action for a key press. Shift is ignored if the key isn't bound with it,
+    /// as it's already part of keysyms such as J or ISO_Left_Tab.
+    pub fn get(&self, ksym: u32, state: c_uint) -> Option<&Action> {
+	let mods = state & (ShiftMask|ControlMask|Mod1Mask);
+	self.keys.get(&(ksym, mods))
+	    .or_else(|| self.keys.get(&(ksym, mods & !ShiftMask)))
+    }
+
+    /// Binds key, named as in parse_key, to action. "none" unbinds it.
+    pub fn bind(&mut self, key: &str, action: &str) -> Result<(), String> {
+	let key = parse_key(key)?;
+	if action == "none" {
+	    self.keys.remove(&key);
+	    return Ok(());
+	}
+	let action = Action::parse(action).ok_or_else(|| format!("unknown action '{}'", action))?;
+	self.keys.insert(key, action);
+	Ok(())
+    }
+}
+
+/// Parses a key named by its X keysym, such as Tab, Return or a,
+/// optionally prefixed by modifiers: C- for Control, M- for Alt and S- for Shift.
+/// Returns the keysym and modifier mask.
+pub fn parse_key(name: &str) -> Result<(u32, c_uint), String> {
+    let mut mods: c_uint = 0;
+    let mut keysym = name;
+    while keysym.len() > 2 && keysym.as_bytes()[1] == b'-' {
+	mods |= match keysym.as_bytes()[0] {
+	    b'C' => ControlMask,
+	    b'M' => Mod1Mask,
+	    b'S' => ShiftMask,
+	    _ => return Err(format!("unknown modifier in '{}'", name)),
+	};
+	keysym = &keysym[2..];
+    }
+    let cname = CString::new(keysym).map_err(|_| format!("unknown key '{}'", name))?;
+    let ksym = unsafe{XStringToKeysym(cname.as_ptr())} as u32;
+    if ksym == 0 { // NoSymbol
+	return Err(format!("unknown key '{}'", name));
+    }
+    Ok((ksym, mods))
+}
@@ -0,0 +1,262 @@
+use fontconfig::fontconfig::{FcPattern, FcNameParse, FcConfigSubstitute, FcDefaultSubstitute,
+			     FcFontMatch, FcPatternGetString, FcPatternGetInteger, FcPatternGetDouble,
+			     FcPatternDestroy, FcPatternDuplicate, FcPatternAddCharSet, FcPatternAddBool,
+			     FcCharSetCreate, FcCharSetAddChar, FcCharSetDestroy,
+			     FcMatchPattern, FcResultMatch, FcChar8};
+use fontdue::{Font, FontSettings, Metrics};
+use libc::{c_int, c_uint};
+use std::{collections::HashMap, ffi::CStr, fmt, fs, ptr};
+
+use crate::additional_bindings::fontconfig::{FC_FILE, FC_INDEX, FC_PIXEL_SIZE,
+					     FC_SCALABLE, FC_CHARSET, FC_COLOR, FcTrue, FcFalse};
+use crate::backend::Color;
+use crate::result::*;
+
+/// Draws into memory with no display at all, for backends that hand over pixel buffers.
+/// Pixels are 0xAARRGGBB, as wl_shm's ARGB8888.
+/// Fonts are found with fontconfig like Xft does, and rasterized by fontdue.
+#[derive(Debug, Default)]
+pub struct Canvas {
+    pub w: usize,
+    pub h: usize,
+    pub pixels: Vec<u32>,
+    faces: Vec<Face>,
+    pattern: Option<*mut FcPattern>, // of the primary font, fallbacks are matched against it
+    fallbacks: HashMap<char, usize>, // which face draws chars missing from the loaded fonts
+    glyphs: HashMap<(usize, char), (Metrics, Vec<u8>)>,
+    images: Vec<(Vec<u32>, usize)>, // premultiplied, with their size
+}
+
+// A font at the pixel size its pattern asked for
+struct Face {
+    file: String,
+    font: Font,
+    px: f32,
+    ascent: c_int,
+    height: c_uint,
+}
+
+impl fmt::Debug for Face {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "Face({}, {}px)", self.file, self.px)
+    }
+}
+
+impl Face {
+    // Loads whatever fontconfig matches pattern with
+    fn open(pattern: *mut FcPattern) -> CompResult<Self> {
+	unsafe {
+	    FcConfigSubstitute(ptr::null_mut(), pattern, FcMatchPattern);
+	    FcDefaultSubstitute(pattern);
+	    let mut result = FcResultMatch;
+	    let matched = FcFontMatch(ptr::null_mut(), pattern, &mut result);
+	    if matched == ptr::null_mut() {
+		return Die::stderr("error, no fonts found".to_owned());
+	    }
+	    let mut file: *mut FcChar8 = ptr::null_mut();
+	    let mut index: c_int = 0;
+	    let mut px: f64 = 0.0;
+	    let found = FcPatternGetString(matched, FC_FILE, 0, &mut file) == FcResultMatch;
+	    FcPatternGetInteger(matched, FC_INDEX, 0, &mut index);
+	    FcPatternGetDouble(matched, FC_PIXEL_SIZE, 0, &mut px);
+	    let file = if found {
+		CStr::from_ptr(file as *const _).to_string_lossy().to_string()
+	    } else {
+		String::new()
+	    };
+	    FcPatternDestroy(matched);
+	    if !found {
+		return Die::stderr("error, matched font has no file".to_owned());
+	    }
+	    let px = if px > 0.0 {px as f32} else {12.0};
+	    let data = fs::read(&file)
+		.map_err(|err| Die::Stderr(format!("error, cannot read font {}: {}", file, err)))?;
+	    let font = Font::from_bytes(data, FontSettings{collection_index: index as u32, scale: px,
+							   ..FontSettings::default()})
+		.map_err(|err| Die::Stderr(format!("error, cannot load font {}: {}", file, err)))?;
+	    let (ascent, descent) = font.horizontal_line_metrics(px)
+		.map(|m| (m.ascent.round() as c_int, m.descent.round() as c_int))
+		.unwrap_or((px as c_int, 0));
+	    Ok(Self{file, font, px, ascent, height: (ascent - descent) as c_uint})
+	}
+    }
+
+    fn has(&self, c: char) -> bool {
+	self.font.lookup_glyph_index(c) != 0
+    }
+}
+
+impl Drop for Canvas {
+    fn drop(&mut self) {
+	if let Some(pattern) = self.pattern {
+	    unsafe{FcPatternDestroy(pattern)};
+	}
+    }
+}
+
+fn argb(color: Color) -> u32 {
+    0xff00_0000 | (color.r as u32) << 16 | (color.g as u32) << 8 | color.b as u32
+}
+
+// Composites a premultiplied ARGB src over an opaque dst
+fn over(dst: u32, src: u32) -> u32 {
+    let a = src >> 24;
+    let channel = |shift: u32| {
+	let s = (src >> shift) & 0xff;
+	let d = (dst >> shift) & 0xff;
+	(s + d * (255 - a) / 255).min(255) << shift
+    };
+    0xff00_0000 | channel(16) | channel(8) | channel(0)
+}
+
+impl Canvas {
+    /// Clears to transparent black at the new size
+    pub fn resize(&mut self, w: usize, h: usize) {
+	self.w = w;
+	self.h = h;
+	self.pixels = vec![0; w*h];
+    }
+
+    pub fn load_fonts(&mut self, names: &[String]) -> CompResult<()> {
+	for name in names {
+	    let cname = format!("{}\0", name);
+	    unsafe {
+		let pattern = FcNameParse(cname.as_ptr() as *const FcChar8);
+		if pattern == ptr::null_mut() {
+		    return Die::stderr(format!("error, cannot parse font name to pattern: '{}'", name));
+		}
+		if self.pattern.is_none() {
+		    self.pattern = Some(FcPatternDuplicate(pattern));
+		}
+		let face = Face::open(pattern);
+		FcPatternDestroy(pattern);
+		self.faces.push(face?);
+	    }
+	}
+	Ok(())
+    }
+
+    pub fn font_height(&self) -> c_uint {
+	self.faces[0].height
+    }
+
+    pub fn max_font_height(&self) -> c_uint {
+	self.faces.iter().map(|f| f.height).max().unwrap_or(0)
+    }
+
+    // The face to draw c with, looking for a fallback if no font has it.
+    // If nothing does, the first font draws whatever it has for missing glyphs.
+    fn face_for(&mut self, c: char) -> usize {
+	if let Some(found) = self.faces.iter().position(|face| face.has(c)) {
+	    return found;
+	}
+	if let Some(found) = self.fallbacks.get(&c) {
+	    return *found;
+	}
+	let found = self.pattern.and_then(|primary| unsafe {
+	    let charset = FcCharSetCreate();
+	    FcCharSetAddChar(charset, c as u32);
+	    let pattern = FcPatternDuplicate(primary);
+	    FcPatternAddCharSet(pattern, FC_CHARSET, charset);
+	    FcPatternAddBool(pattern, FC_SCALABLE, FcTrue);
+	    FcPatternAddBool(pattern, FC_COLOR, FcFalse);
+	    let face = Face::open(pattern);
+	    FcCharSetDestroy(charset);
+	    FcPatternDestroy(pattern);
+	    face.ok().filter(|face| face.has(c))
+	}).map(|face| {
+	    self.faces.push(face);
+	    self.faces.len()-1
+	}).unwrap_or(0);
+	self.fallbacks.insert(c, found);
+	found
+    }
+
+    fn glyph(&mut self, face: usize, c: char) -> &(Metrics, Vec<u8>) {
+	let faces = &self.faces;
+	self.glyphs.entry((face, c))
+	    .or_insert_with(|| faces[face].font.rasterize(c, faces[face].px))
+    }
+
+    /// Advances are rounded to whole pixels like Xft's, so that text split where its
+    /// colors change is as wide as the whole
+    pub fn text_width(&mut self, text: &str) -> c_uint {
+	let mut w = 0;
+	for c in text.chars() {
+	    let face = self.face_for(c);
+	    w += self.glyph(face, c).0.advance_width.round() as c_uint;
+	}
+	w
+    }
+
+    pub fn text(&mut self, x: c_int, y: c_int, h: c_uint, text: &str, color: Color) {
+	let mut pen = x;
+	for c in text.chars() {
+	    let face = self.face_for(c);
+	    let (ascent, height) = (self.faces[face].ascent, self.faces[face].height);
+	    let baseline = y + (h as c_int - height as c_int) / 2 + ascent;
+	    let (metrics, coverage) = self.glyph(face, c).clone();
+	    let gx = pen + metrics.xmin;
+	    let gy = baseline - (metrics.ymin + metrics.height as c_int);
+	    for row in 0..metrics.height {
+		for col in 0..metrics.width {
+		    let a = coverage[row*metrics.width + col] as u32;
+		    if a == 0 {
+			continue;
+		    }
+		    let premultiplied = a << 24
+			| (color.r as u32 * a / 255) << 16
+			| (color.g as u32 * a / 255) << 8
+			| color.b as u32 * a / 255;
+		    self.blend(gx + col as c_int, gy + row as c_int, premultiplied);
+		}
+	    }
+	    pen += metrics.advance_width.round() as c_int;
+	}
+    }
+
+    fn blend(&mut self, x: c_int, y: c_int, src: u32) {
+	if x >= 0 && y >= 0 && (x as usize) < self.w && (y as usize) < self.h {
+	    let i = y as usize * self.w + x as usize;
+	    self.pixels[i] = over(self.pixels[i], src);
+	}
+    }
+
+    fn fill(&mut self, x: c_int, y: c_int, w: c_int, h: c_int, pixel: u32) {
+	let (x0, y0) = (x.max(0) as usize, y.max(0) as usize);
+	let x1 = ((x + w).max(0) as usize).min(self.w);
+	let y1 = ((y + h).max(0) as usize).min(self.h);
+	for row in y0..y1 {
+	    for px in &mut self.pixels[row*self.w+x0.min(x1)..row*self.w+x1] {
+		*px = pixel;
+	    }
+	}
+    }
+
+    pub fn rect(&mut self, x: c_int, y: c_int, w: c_uint, h: c_uint, color: Color, filled: bool) {
+	let (w, h, pixel) = (w as c_int, h as c_int, argb(color));
+	if filled {
+	    self.fill(x, y, w, h, pixel);
+	} else {
+	    self.fill(x, y, w, 1, pixel);
+	    self.fill(x, y + h - 1, w, 1, pixel);
+	    self.fill(x, y, 1, h, pixel);
+	    self.fill(x + w - 1, y, 1, h, pixel);
+	}
+    }
+
+    pub fn load_image(&mut self, pixels: Vec<u32>, size: c_uint) -> usize {
+	self.images.push((pixels, size as usize));
+	self.images.len()-1
+    }
+
+    pub fn draw_image(&mut self, id: usize, x: c_int, y: c_int) {
+	let (pixels, size) = std::mem::take(&mut self.images[id]);
+	for row in 0..size {
+	    for col in 0..size {
+		self.blend(x + col as c_int, y + row as c_int, pixels[row*size + col]);
+	    }
+	}
+	self.images[id] = (pixels, size);
+    }
+}
@@ -3,7 +3,9 @@ use itertools::Itertools;
 use yaml_rust::yaml::Yaml;
 use regex::RegexBuilder;
 
-use crate::config::{Clrs::*, Schemes::*, Config, DefaultWidth};
+use crate::config::{Clrs::{self, *}, Schemes::{self, *}, Config, DefaultWidth};
+use crate::matching::MATCHERS;
+use crate::backend::BACKENDS;
 use crate::result::*;
 
 lazy_static::lazy_static! {
@@ -13,7 +15,9 @@ lazy_static::lazy_static! {
             .pop()
             .unwrap()
         };
-    pub static ref CLAP_FLAGS: ArgMatches<'static> = App::from_yaml(&YAML).get_matches();     
+    pub static ref CLAP_FLAGS: ArgMatches<'static> = App::from_yaml(&YAML)
+	.args(&crate::plugin::clap_args())
+	.get_matches();
 }
 
 pub fn validate(config: &mut Config) -> CompResult<()> {
@@ -47,10 +51,10 @@ pub fn validate(config: &mut Config) -> CompResult<()> {
 	}
     }
     
-    let color_regex = RegexBuilder::new("^#([A-Fa-f0-9]{6}|[A-Fa-f0-9]{3})\0$")
-	.case_insensitive(true)
-	.build().map_err(|_| Die::Stderr("Could not build regex"
-					 .to_owned()))?;
+    // list_path
+    if CLAP_FLAGS.occurrences_of("list_path") == 1 {
+	return Die::stdout(crate::executables::list().join("\n"));
+    }
 
     // bottom
     if CLAP_FLAGS.occurrences_of("bottom") == 1 {
@@ -95,46 +99,52 @@ pub fn validate(config: &mut Config) -> CompResult<()> {
 
     // color_normal_background
     if let Some(color) = CLAP_FLAGS.value_of("color_normal_background") {
-	let mut color = color.to_string();
-	color.push('\0');
-	color_regex.find_iter(&color).nth(0)
-	    .ok_or(Die::Stderr("--nb: Color must be in hex format (#123456 or #123)"
-			       .to_owned()))?;
-	config.colors[SchemeNorm as usize][ColBg as usize]
-	    .copy_from_slice(color.as_bytes());
+	set_color(config, SchemeNorm, ColBg, color, "--nb")?;
     }
 
     // color_normal_foreground
     if let Some(color) = CLAP_FLAGS.value_of("color_normal_foreground") {
-	let mut color = color.to_string();
-	color.push('\0');
-	color_regex.find_iter(&color).nth(0)
-	    .ok_or(Die::Stderr("--nf: Color must be in hex format (#123456 or #123)"
-			       .to_owned()))?;
-	config.colors[SchemeNorm as usize][ColFg as usize]
-	    .copy_from_slice(color.as_bytes());
+	set_color(config, SchemeNorm, ColFg, color, "--nf")?;
     }
 
     // color_selected_background
     if let Some(color) = CLAP_FLAGS.value_of("color_selected_background") {
-	let mut color = color.to_string();
-	color.push('\0');
-	color_regex.find_iter(&color).nth(0)
-	    .ok_or(Die::Stderr("--sb: Color must be in hex format (#123456 or #123)"
-			       .to_owned()))?;
-	config.colors[SchemeSel as usize][ColBg as usize]
-	    .copy_from_slice(color.as_bytes());
+	set_color(config, SchemeSel, ColBg, color, "--sb")?;
     }
 
     // color_selected_foreground
     if let Some(color) = CLAP_FLAGS.value_of("color_selected_foreground") {
-	let mut color = color.to_string();
-	color.push('\0');
-	color_regex.find_iter(&color).nth(0)
-	    .ok_or(Die::Stderr("--sf: Color must be in hex format (#123456 or #123)"
-			       .to_owned()))?;
-	config.colors[SchemeSel as usize][ColFg as usize]
-	    .copy_from_slice(color.as_bytes());
+	set_color(config, SchemeSel, ColFg, color, "--sf")?;
+    }
+
+    // color_marked_background
+    if let Some(color) = CLAP_FLAGS.value_of("color_marked_background") {
+	set_color(config, SchemeMark, ColBg, color, "--mb")?;
+    }
+
+    // color_marked_foreground
+    if let Some(color) = CLAP_FLAGS.value_of("color_marked_foreground") {
+	set_color(config, SchemeMark, ColFg, color, "--mf")?;
+    }
+
+    // color_normal_highlight_background
+    if let Some(color) = CLAP_FLAGS.value_of("color_normal_highlight_background") {
+	set_color(config, SchemeNormHl, ColBg, color, "--nhb")?;
+    }
+
+    // color_normal_highlight_foreground
+    if let Some(color) = CLAP_FLAGS.value_of("color_normal_highlight_foreground") {
+	set_color(config, SchemeNormHl, ColFg, color, "--nhf")?;
+    }
+
+    // color_selected_highlight_background
+    if let Some(color) = CLAP_FLAGS.value_of("color_selected_highlight_background") {
+	set_color(config, SchemeSelHl, ColBg, color, "--shb")?;
+    }
+
+    // color_selected_highlight_foreground
+    if let Some(color) = CLAP_FLAGS.value_of("color_selected_highlight_foreground") {
+	set_color(config, SchemeSelHl, ColFg, color, "--shf")?;
     }
 
     // window
@@ -149,6 +159,121 @@ pub fn validate(config: &mut Config) -> CompResult<()> {
 	config.nostdin = true;
     }
 
+    // incremental
+    if CLAP_FLAGS.occurrences_of("incremental") == 1 {
+	config.incremental = true;
+    }
+
+    // multi
+    if CLAP_FLAGS.occurrences_of("multi") == 1 {
+	config.multi = true;
+    }
+
+    // delimiter
+    if let Some(delimiter) = CLAP_FLAGS.value_of("delimiter") {
+	set_delimiter(config, delimiter, "--delimiter")?;
+    }
+
+    // display_field
+    if let Some(field) = CLAP_FLAGS.value_of("display_field") {
+	config.display_field = parse_field(field, "--display-field")?;
+    }
+
+    // output_field
+    if let Some(field) = CLAP_FLAGS.value_of("output_field") {
+	config.output_field = Some(parse_field(field, "--output-field")?);
+    }
+
+    // history_key
+    if let Some(key) = CLAP_FLAGS.value_of("history_key") {
+	config.history_key = Some(key.to_string());
+    }
+
+    // markup
+    if CLAP_FLAGS.occurrences_of("markup") == 1 {
+	config.markup = true;
+    }
+
+    // mode_cmd
+    if let Some(command) = CLAP_FLAGS.value_of("mode_cmd") {
+	config.mode_cmd = Some(command.to_string());
+    }
+    if config.mode_cmd.is_some() {
+	config.nostdin = true; // items come from the helper
+    }
+
+    // modes
+    if let Some(modes) = CLAP_FLAGS.value_of("modes") {
+	config.modes = modes.split(',').map(|m| m.to_string()).collect();
+    }
+
+    // windows
+    if CLAP_FLAGS.occurrences_of("windows") == 1 {
+	add_mode(config, "window");
+    }
+
+    // drun
+    if CLAP_FLAGS.occurrences_of("drun") == 1 {
+	add_mode(config, "drun");
+    }
+
+    // run
+    if CLAP_FLAGS.occurrences_of("run") == 1 {
+	add_mode(config, "run");
+    }
+    if config.modes.iter().any(|m| m.len() == 0) {
+	return Die::stderr("--modes: Mode names must not be empty".to_owned());
+    }
+    if config.incremental && config.modes.len() > 0 { // clap only catches this between flags
+	return Die::stderr("--incremental: Cannot be used with --modes".to_owned());
+    }
+    if config.modes.len() > 0 && !config.modes.iter().any(|m| m == "dmenu") {
+	config.nostdin = true; // no mode reads it
+    }
+
+    // terminal
+    if let Some(terminal) = CLAP_FLAGS.value_of("terminal") {
+	config.terminal = Some(terminal.to_string());
+    }
+
+    // nofuzz, from the fuzzy plugin
+    if CLAP_FLAGS.occurrences_of("nofuzz") == 1 && config.matching == "fuzzy" {
+	config.matching = "tokens".to_owned();
+    }
+
+    // matching
+    if let Some(matching) = CLAP_FLAGS.value_of("matching") {
+	set_matching(config, matching, "--matching")?;
+    }
+
+    // backend
+    if let Some(backend) = CLAP_FLAGS.value_of("backend") {
+	set_backend(config, backend, "--backend")?;
+    }
+
+    // render_to
+    if let Some(path) = CLAP_FLAGS.value_of("render_to") {
+	config.render_to = Some(path.to_string());
+    }
+
+    // replay
+    if let Some(path) = CLAP_FLAGS.value_of("replay") {
+	config.replay = Some(path.to_string());
+    }
+
+    // vi
+    if CLAP_FLAGS.occurrences_of("vi") == 1 {
+	set_vi(config);
+    }
+
+    // bind
+    for binding in CLAP_FLAGS.values_of("bind").into_iter().flatten() {
+	match binding.rfind(':') {
+	    Some(colon) => set_binding(config, &binding[..colon], &binding[colon+1..], "--bind")?,
+	    None => return Die::stderr(format!("--bind: expected KEY:ACTION, got '{}'", binding)),
+	}
+    }
+
     // render_minheight
     if let Some(minheight) = CLAP_FLAGS.value_of("render_minheight") {
 	config.render_minheight = minheight.parse::<u32>()
@@ -174,30 +299,107 @@ pub fn validate(config: &mut Config) -> CompResult<()> {
     
     // render_default_width
     if let Some(arg) = CLAP_FLAGS.value_of("render_default_width") {
-	if !arg.contains("=") {
-	    config.render_default_width = match arg {
-		"min" => DefaultWidth::Min,
-		"items" => DefaultWidth::Items,
-		"max" => {
-		    config.render_rightalign = true;
-		    DefaultWidth::Max
-		},
-		_ => return Die::stderr("--render_default_width: invalid arguement".to_owned()),
-	    }
-	} else {
-	    let vec: Vec<&str> = arg.split("=").collect();
-	    if vec.len() != 2 || (vec.len() > 0 && vec[0] != "custom") {
-		return Die::stderr("Incorrect format for --render_default_width, \
-				    see help for details".to_owned());
-	    }
-	    let width = vec[1].parse::<u8>();
-	    if width.is_err() || *width.as_ref().unwrap() > 100 {
-		return Die::stderr("--render_default_width: custom width \
-				      must be a positive integer".to_owned());
-	    }
-	    config.render_default_width = DefaultWidth::Custom(width.unwrap());
-	}
+	set_default_width(config, arg, "--render_default_width")?;
     }
 
     Ok(())
 }
+
+/// Sets a color from a hex string, `flag` is used for error messages
+pub fn set_color(config: &mut Config, scheme: Schemes, clr: Clrs, color: &str, flag: &str) -> CompResult<()> {
+    let mut color = color.to_string();
+    color.push('\0');
+    RegexBuilder::new("^#([A-Fa-f0-9]{6}|[A-Fa-f0-9]{3})\0$")
+	.case_insensitive(true)
+	.build().map_err(|_| Die::Stderr("Could not build regex"
+					 .to_owned()))?
+	.find_iter(&color).nth(0)
+	.ok_or(Die::Stderr(format!("{}: Color must be in hex format (#123456 or #123)",
+				   flag)))?;
+    let dest = &mut config.colors[scheme as usize][clr as usize];
+    *dest = [0; 8];
+    dest[..color.len()].copy_from_slice(color.as_bytes());
+    Ok(())
+}
+
+/// Sets the field delimiter, with `\t` for a tab. `flag` is used for error messages
+pub fn set_delimiter(config: &mut Config, delimiter: &str, flag: &str) -> CompResult<()> {
+    if delimiter.len() == 0 {
+	return Die::stderr(format!("{}: Delimiter must not be empty", flag));
+    }
+    config.delimiter = Some(delimiter.replace("\\t", "\t"));
+    Ok(())
+}
+
+/// Parses a 1-based field number, `flag` is used for error messages
+pub fn parse_field(field: &str, flag: &str) -> CompResult<usize> {
+    field.parse::<usize>().ok().filter(|f| *f > 0)
+	.ok_or(Die::Stderr(format!("{}: Field must be a positive integer", flag)))
+}
+
+/// Adds a mode to those in --modes, unless it's already there
+pub fn add_mode(config: &mut Config, name: &str) {
+    if !config.modes.iter().any(|m| m == name) {
+	config.modes.push(name.to_string());
+    }
+}
+
+/// Sets the matcher by name, `flag` is used for error messages
+pub fn set_matching(config: &mut Config, name: &str, flag: &str) -> CompResult<()> {
+    if !MATCHERS.contains(&name) {
+	return Die::stderr(format!("{}: unknown matcher '{}', expected one of {}",
+				   flag, name, MATCHERS.join(", ")));
+    }
+    config.matching = name.to_string();
+    Ok(())
+}
+
+/// Sets the backend by name, `flag` is used for error messages
+pub fn set_backend(config: &mut Config, name: &str, flag: &str) -> CompResult<()> {
+    if !BACKENDS.contains(&name) {
+	return Die::stderr(format!("{}: unknown backend '{}', expected one of {}",
+				   flag, name, BACKENDS.join(", ")));
+    }
+    config.backend = name.to_string();
+    Ok(())
+}
+
+/// Turns on --vi, making Escape enter normal mode
+pub fn set_vi(config: &mut Config) {
+    config.vi = true;
+    config.bindings.vi();
+}
+
+/// Binds a key to an action, `flag` is used for error messages
+pub fn set_binding(config: &mut Config, key: &str, action: &str, flag: &str) -> CompResult<()> {
+    config.bindings.bind(key, action)
+	.map_err(|err| Die::Stderr(format!("{}: {}", flag, err)))
+}
+
+/// Parses a render_default_width value, `flag` is used for error messages
+pub fn set_default_width(config: &mut Config, arg: &str, flag: &str) -> CompResult<()> {
+    if !arg.contains("=") {
+	config.render_default_width = match arg {
+	    "min" => DefaultWidth::Min,
+	    "items" => DefaultWidth::Items,
+	    "max" => {
+		config.render_rightalign = true;
+		DefaultWidth::Max
+	    },
+	    _ => return Die::stderr(format!("{}: invalid arguement", flag)),
+	}
+    } else {
+	let vec: Vec<&str> = arg.split("=").collect();
+	if vec.len() != 2 || (vec.len() > 0 && vec[0] != "custom") {
+	    return Die::stderr(format!("Incorrect format for {}, \
+					see help for details", flag));
+	}
+	let width = vec[1].parse::<u8>();
+	if width.is_err() || *width.as_ref().unwrap() > 100 {
+	    return Die::stderr(format!("{}: custom width \
+					must be a positive integer", flag));
+	}
+	config.render_default_width = DefaultWidth::Custom(width.unwrap());
+    }
+    Ok(())
+}
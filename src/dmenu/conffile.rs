@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::PathBuf;
+use toml::Value;
+use toml::value::Table;
+
+use crate::clapflags::{CLAP_FLAGS, add_mode, parse_field, set_color, set_default_width, set_delimiter,
+		       set_matching, set_backend, set_binding, set_vi};
+use crate::config::{Clrs::*, Schemes::*, Config};
+use crate::util::xdg_dir;
+use crate::result::*;
+
+/// Location of the config file: $XDG_CONFIG_HOME/dmenu-rs/config.toml
+pub fn path() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+	.map(|dir| dir.join("dmenu-rs").join("config.toml"))
+}
+
+/// Reads the config file (if it exists) into config
+/// Keys are the long names of command line flags. Top level keys are applied
+/// first, then the keys in [profile.NAME] if --profile NAME was passed.
+/// This is ran before clapflags::validate, so command line flags always win.
+pub fn load(config: &mut Config) -> CompResult<()> {
+    let profile = CLAP_FLAGS.value_of("profile");
+    let path = match path() {
+	Some(path) if path.exists() => path,
+	_ => return match profile {
+	    Some(name) => Die::stderr(format!("--profile: no config file to load profile '{}' from",
+					      name)),
+	    None => Ok(()),
+	},
+    };
+
+    let contents = fs::read_to_string(&path)
+	.map_err(|err| Die::Stderr(format!("Could not read {}: {}", path.display(), err)))?;
+    let root = match contents.parse::<Value>() {
+	Ok(Value::Table(table)) => table,
+	Ok(_) => return Die::stderr(format!("{}: expected a table", path.display())),
+	Err(err) => return Die::stderr(format!("{}: {}", path.display(), err)),
+    };
+
+    apply(config, &root, "")?;
+    if let Some(name) = profile {
+	match root.get("profile").and_then(|p| p.get(name)).and_then(Value::as_table) {
+	    Some(table) => apply(config, table, &format!("profile.{}.", name))?,
+	    None => return Die::stderr(format!("--profile: profile '{}' not found in {}",
+					       name, path.display())),
+	}
+    }
+    Ok(())
+}
+
+/// Names of the runtime plugins to load, from the top level `plugins` key
+/// Errors are left for load to report
+pub fn plugins() -> Vec<String> {
+    path().and_then(|path| fs::read_to_string(path).ok())
+	.and_then(|contents| contents.parse::<Value>().ok())
+	.and_then(|root| root.get("plugins").and_then(Value::as_array).cloned())
+	.map(|plugins| plugins.iter().filter_map(|p| p.as_str().map(|p| p.to_string())).collect())
+	.unwrap_or_default()
+}
+
+/// Applies a table of options named like command line flags
+/// prefix is prepended to key names in error messages
+pub fn apply(config: &mut Config, table: &Table, prefix: &str) -> CompResult<()> {
+    for (key, value) in table {
+	let name = format!("{}{}", prefix, key);
+	match key.as_str() {
+	    "profile" if prefix.len() == 0 => {}, // selected in load
+	    "plugins" if prefix.len() == 0 => {}, // loaded in plugin::load
+	    "bottom"            => config.topbar = !boolean(value, &name)?,
+	    "fast"              => config.fast = boolean(value, &name)?,
+	    "insensitive"       => config.case_sensitive = !boolean(value, &name)?,
+	    "lines"             => config.lines = integer(value, &name)? as u32,
+	    "monitor"           => config.mon = integer(value, &name)? as i32,
+	    "prompt"            => config.prompt = string(value, &name)?,
+	    "font" => {
+		let fonts = match value {
+		    Value::Array(fonts) => fonts.iter().map(|f| string(f, &name))
+			.collect::<CompResult<Vec<String>>>()?,
+		    _ => vec![string(value, &name)?],
+		};
+		let default = config.fontstrings.pop().unwrap();
+		config.fontstrings = fonts;
+		config.fontstrings.push(default);
+	    },
+	    "nb" => set_color(config, SchemeNorm, ColBg, &string(value, &name)?, &name)?,
+	    "nf" => set_color(config, SchemeNorm, ColFg, &string(value, &name)?, &name)?,
+	    "sb" => set_color(config, SchemeSel,  ColBg, &string(value, &name)?, &name)?,
+	    "sf" => set_color(config, SchemeSel,  ColFg, &string(value, &name)?, &name)?,
+	    "mb" => set_color(config, SchemeMark, ColBg, &string(value, &name)?, &name)?,
+	    "mf" => set_color(config, SchemeMark, ColFg, &string(value, &name)?, &name)?,
+	    "nhb" => set_color(config, SchemeNormHl, ColBg, &string(value, &name)?, &name)?,
+	    "nhf" => set_color(config, SchemeNormHl, ColFg, &string(value, &name)?, &name)?,
+	    "shb" => set_color(config, SchemeSelHl,  ColBg, &string(value, &name)?, &name)?,
+	    "shf" => set_color(config, SchemeSelHl,  ColFg, &string(value, &name)?, &name)?,
+	    "render_minheight"  => config.render_minheight = integer(value, &name)? as u32,
+	    "render_overrun"    => {
+		config.render_overrun = boolean(value, &name)?;
+		config.render_flex |= config.render_overrun;
+	    },
+	    "render_flex"       => config.render_flex = boolean(value, &name)?,
+	    "render_rightalign" => config.render_rightalign = boolean(value, &name)?,
+	    "render_default_width" => set_default_width(config, &string(value, &name)?, &name)?,
+	    "multi"             => config.multi = boolean(value, &name)?,
+	    "incremental"       => config.incremental = boolean(value, &name)?,
+	    "delimiter"         => set_delimiter(config, &string(value, &name)?, &name)?,
+	    "display-field"     => config.display_field = parse_field(&integer(value, &name)?.to_string(), &name)?,
+	    "output-field"      => config.output_field = Some(parse_field(&integer(value, &name)?.to_string(), &name)?),
+	    "history-key"       => config.history_key = Some(string(value, &name)?),
+	    "markup"            => config.markup = boolean(value, &name)?,
+	    "mode-cmd"          => config.mode_cmd = Some(string(value, &name)?),
+	    "modes" => {
+		config.modes = match value {
+		    Value::Array(modes) => modes.iter().map(|m| string(m, &name))
+			.collect::<CompResult<Vec<String>>>()?,
+		    _ => string(value, &name)?.split(',').map(|m| m.to_string()).collect(),
+		};
+	    },
+	    "terminal"          => config.terminal = Some(string(value, &name)?),
+	    "matching"          => set_matching(config, &string(value, &name)?, &name)?,
+	    "backend"           => set_backend(config, &string(value, &name)?, &name)?,
+	    "bindings" => {
+		let table = value.as_table()
+		    .ok_or(Die::Stderr(format!("config file: {} must be a table", name)))?;
+		for (key, action) in table {
+		    let name = format!("{}.{}", name, key);
+		    set_binding(config, key, &string(action, &name)?, &name)?;
+		}
+	    },
+	    "vi"                => if boolean(value, &name)? {
+		set_vi(config);
+	    },
+	    "windows"           => if boolean(value, &name)? {
+		add_mode(config, "window");
+	    },
+	    "drun"              => if boolean(value, &name)? {
+		add_mode(config, "drun");
+	    },
+	    "run"               => if boolean(value, &name)? {
+		add_mode(config, "run");
+	    },
+	    _ => return Die::stderr(format!("config file: unknown option '{}'", name)),
+	}
+    }
+    Ok(())
+}
+
+fn boolean(value: &Value, name: &str) -> CompResult<bool> {
+    value.as_bool()
+	.ok_or(Die::Stderr(format!("config file: {} must be true or false", name)))
+}
+
+fn integer(value: &Value, name: &str) -> CompResult<i64> {
+    value.as_integer().filter(|i| *i >= 0)
+	.ok_or(Die::Stderr(format!("config file: {} must be a non-negative integer", name)))
+}
+
+fn string(value: &Value, name: &str) -> CompResult<String> {
+    value.as_str().map(|s| s.to_string())
+	.ok_or(Die::Stderr(format!("config file: {} must be a string", name)))
+}
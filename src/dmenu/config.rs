@@ -2,7 +2,9 @@ use x11::xlib::Window;
 use std::mem::MaybeUninit;
 use libc::{c_int, c_uint};
 
-pub enum Schemes { SchemeNorm, SchemeSel, SchemeOut, SchemeLast }
+use crate::bindings::Bindings;
+
+pub enum Schemes { SchemeNorm, SchemeSel, SchemeOut, SchemeMark, SchemeNormHl, SchemeSelHl, SchemeLast }
 pub enum Clrs    { ColFg, ColBg }
 pub use Schemes::*;
 pub use Clrs::*;
@@ -33,6 +35,22 @@ pub struct Config {
     pub render_rightalign: bool,
     pub render_default_width: DefaultWidth,
     pub nostdin: bool,
+    pub incremental: bool,
+    pub multi: bool,
+    pub delimiter: Option<String>,
+    pub display_field: usize,
+    pub output_field: Option<usize>,
+    pub history_key: Option<String>,
+    pub markup: bool,
+    pub mode_cmd: Option<String>,
+    pub modes: Vec<String>,
+    pub terminal: Option<String>,
+    pub matching: String,
+    pub backend: String,
+    pub render_to: Option<String>,
+    pub replay: Option<String>,
+    pub bindings: Bindings,
+    pub vi: bool,
 }
 
 pub struct ConfigDefault{}
@@ -57,6 +75,22 @@ impl Default for Config {
 		render_rightalign:    ConfigDefault::render_rightalign(),
 		render_default_width: ConfigDefault::render_default_width(),
 		nostdin:              ConfigDefault::nostdin(),
+		incremental:          ConfigDefault::incremental(),
+		multi:                ConfigDefault::multi(),
+		delimiter:            ConfigDefault::delimiter(),
+		display_field:        ConfigDefault::display_field(),
+		output_field:         ConfigDefault::output_field(),
+		history_key:          ConfigDefault::history_key(),
+		markup:               ConfigDefault::markup(),
+		mode_cmd:             ConfigDefault::mode_cmd(),
+		modes:                ConfigDefault::modes(),
+		terminal:             ConfigDefault::terminal(),
+		matching:             ConfigDefault::matching(),
+		backend:              ConfigDefault::backend(),
+		render_to:            ConfigDefault::render_to(),
+		replay:               ConfigDefault::replay(),
+		bindings:             ConfigDefault::bindings(),
+		vi:                   ConfigDefault::vi(),
 	    }
 	}
     }
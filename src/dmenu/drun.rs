@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+use crate::drw::Drw;
+use crate::item::Item;
+use crate::mode::Mode;
+use crate::util::{spawn_in_terminal, xdg_dir};
+use crate::result::*;
+
+/// An application from a .desktop file
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    name: String,
+    keywords: String, // GenericName and Keywords, lowercased, for matching
+    exec: String,
+    icon: Option<String>,
+    terminal: bool,
+    working_dir: Option<String>,
+}
+
+/// Applications from XDG desktop entries, see --drun
+#[derive(Debug, Default)]
+pub struct Drun {
+    entries: Vec<Entry>, // by item index
+}
+
+impl Mode for Drun {
+    fn name(&self) -> &str {
+	"drun"
+    }
+    fn items(&mut self, _drw: &mut Drw) -> CompResult<Vec<Item>> {
+	self.entries = entries();
+	let mut items = Vec::new();
+	for entry in &self.entries {
+	    let mut item = Item::new(entry.name.clone(), false);
+	    item.payload = Some(Arc::new(entry.path.to_string_lossy().into_owned()));
+	    if let Some(icon) = entry.icon.as_ref().filter(|icon| icon.starts_with('/')) {
+		item.set_icon(icon.clone()); // names need an icon theme, only paths are shown
+	    }
+	    items.push(item);
+	}
+	Ok(items)
+    }
+    /// Matches on Name like usual, followed by matches on GenericName or Keywords
+    fn gen_matches(&mut self, drw: &mut Drw) -> CompResult<Vec<Item>> {
+	let mut matches = drw.builtin_matches()?;
+	let input = drw.input.to_lowercase();
+	if input.len() > 0 {
+	    let found: HashSet<usize> = matches.iter().filter_map(|item| item.index).collect();
+	    for item in drw.get_items() {
+		match item.index {
+		    Some(i) if !found.contains(&i) && self.entries[i].keywords.contains(&input) => {
+			let mut item = item.clone();
+			item.matched.clear();
+			matches.push(item);
+		    },
+		    _ => {},
+		}
+	    }
+	}
+	Ok(matches)
+    }
+    fn dispose(&mut self, drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	let entry = match self.entries.iter().find(|entry| entry.path.to_string_lossy() == output) {
+	    Some(entry) => entry,
+	    None => return Ok(false), // Shift-Return, or anything else that isn't an application
+	};
+	let args = expand(entry)
+	    .ok_or_else(|| Die::Stderr(format!("{}: invalid Exec line", entry.path.display())))?;
+	let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+	if entry.terminal {
+	    spawn_in_terminal(drw, &args)?;
+	} else if let Some((program, rest)) = args.split_first() {
+	    let mut command = Command::new(program);
+	    command.args(rest);
+	    if let Some(dir) = entry.working_dir.as_ref() {
+		command.current_dir(dir);
+	    }
+	    command.spawn()
+		.map_err(|err| Die::Stderr(format!("Could not run {}: {}", program, err)))?;
+	}
+	Ok(recommendation)
+    }
+}
+
+// $XDG_DATA_HOME/applications, then those in $XDG_DATA_DIRS.
+// Earlier directories take precedence for entries with the same desktop file ID
+fn entries() -> Vec<Entry> {
+    let mut dirs: Vec<PathBuf> = xdg_dir("XDG_DATA_HOME", ".local/share").into_iter().collect();
+    let data_dirs = env::var("XDG_DATA_DIRS").ok().filter(|d| d.len() > 0)
+	.unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(env::split_paths(&data_dirs));
+    let desktops: Vec<String> = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default()
+	.split(':').filter(|d| d.len() > 0).map(|d| d.to_string()).collect();
+    let locales = locales();
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    for dir in dirs {
+	let mut files = Vec::new();
+	find(&dir.join("applications"), "", &mut files);
+	for (id, path) in files {
+	    if seen.insert(id) { // hidden ones still shadow those further down
+		entries.extend(parse(&path, &desktops, &locales));
+	    }
+	}
+    }
+    entries.sort_by_key(|entry| entry.name.to_lowercase());
+    entries
+}
+
+// .desktop files under dir along with their IDs, which are paths with / replaced by -
+fn find(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+	Ok(read) => read.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+	Err(_) => return,
+    };
+    paths.sort();
+    for path in paths {
+	let name = path.file_name().unwrap().to_string_lossy().into_owned();
+	if path.is_dir() {
+	    find(&path, &format!("{}{}-", prefix, name), files);
+	} else if name.ends_with(".desktop") {
+	    files.push((format!("{}{}", prefix, name), path));
+	}
+    }
+}
+
+// None for anything that shouldn't be shown
+fn parse(path: &Path, desktops: &[String], locales: &[String]) -> Option<Entry> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut keys = HashMap::new();
+    let mut in_entry = false;
+    for line in contents.lines() {
+	let line = line.trim();
+	if line.starts_with('[') {
+	    in_entry = line == "[Desktop Entry]";
+	} else if in_entry && !line.starts_with('#') {
+	    if let Some(eq) = line.find('=') {
+		keys.insert(line[..eq].trim(), unescape(line[eq+1..].trim()));
+	    }
+	}
+    }
+    let get = |key: &str| keys.get(key).map(|value| value.as_str());
+    let localized = |key: &str| locales.iter()
+	.find_map(|locale| keys.get(format!("{}[{}]", key, locale).as_str()))
+	.or_else(|| keys.get(key)).cloned();
+    let list = |value: Option<&str>| value.unwrap_or("").split(';')
+	.filter(|s| s.len() > 0).map(|s| s.to_string()).collect::<Vec<String>>();
+    if get("Type") != Some("Application") || get("NoDisplay") == Some("true")
+	|| get("Hidden") == Some("true") {
+	return None;
+    }
+    let only = list(get("OnlyShowIn"));
+    if only.len() > 0 && !only.iter().any(|d| desktops.contains(d)) {
+	return None;
+    }
+    if list(get("NotShowIn")).iter().any(|d| desktops.contains(d)) {
+	return None;
+    }
+    if let Some(try_exec) = get("TryExec") {
+	if !executable(try_exec) {
+	    return None;
+	}
+    }
+    let keywords = format!("{};{}", localized("GenericName").unwrap_or_default(),
+			   localized("Keywords").unwrap_or_default());
+    Some(Entry{
+	path: path.to_path_buf(),
+	name: localized("Name")?,
+	keywords: keywords.to_lowercase(),
+	exec: get("Exec")?.to_string(),
+	icon: get("Icon").map(|icon| icon.to_string()),
+	terminal: get("Terminal") == Some("true"),
+	working_dir: get("Path").filter(|dir| dir.len() > 0).map(|dir| dir.to_string()),
+    })
+}
+
+// Locale suffixes to try in order, from LC_ALL, LC_MESSAGES or LANG
+// lang_COUNTRY.ENCODING@MODIFIER gives lang_COUNTRY@MODIFIER, lang_COUNTRY, lang@MODIFIER, lang
+fn locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+	.filter_map(|var| env::var(var).ok()).find(|l| l.len() > 0)
+	.unwrap_or_default();
+    let (locale, modifier) = match locale.find('@') {
+	Some(at) => (&locale[..at], Some(&locale[at+1..])),
+	None => (&locale[..], None),
+    };
+    let locale = locale.split('.').next().unwrap();
+    let (lang, country) = match locale.find('_') {
+	Some(u) => (&locale[..u], Some(&locale[u+1..])),
+	None => (locale, None),
+    };
+    if lang.len() == 0 || lang == "C" || lang == "POSIX" {
+	return Vec::new();
+    }
+    let mut ret = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+	ret.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+	ret.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+	ret.push(format!("{}@{}", lang, modifier));
+    }
+    ret.push(lang.to_string());
+    ret
+}
+
+fn executable(program: &str) -> bool {
+    if program.contains('/') {
+	return Path::new(program).is_file();
+    }
+    env::var_os("PATH").map(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+	.unwrap_or(false)
+}
+
+// Escapes in string values: \s \n \t \r and \\
+fn unescape(value: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+	if c != '\\' {
+	    ret.push(c);
+	    continue;
+	}
+	match chars.next() {
+	    Some('s') => ret.push(' '),
+	    Some('n') => ret.push('\n'),
+	    Some('t') => ret.push('\t'),
+	    Some('r') => ret.push('\r'),
+	    Some(other) => { // left for Exec quoting, such as \" or \$
+		ret.push('\\');
+		ret.push(other);
+	    },
+	    None => ret.push('\\'),
+	}
+    }
+    ret
+}
+
+// Splits Exec into arguments and expands field codes. There are no files to pass,
+// so %f %F %u and %U expand to nothing. None if the quoting is broken
+fn expand(entry: &Entry) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    for arg in split_exec(&entry.exec)? {
+	match arg.as_str() {
+	    "%f" | "%F" | "%u" | "%U" => {},
+	    "%i" => if let Some(icon) = entry.icon.as_ref() {
+		args.push("--icon".to_string());
+		args.push(icon.clone());
+	    },
+	    _ => {
+		let mut expanded = String::new();
+		let mut chars = arg.chars();
+		while let Some(c) = chars.next() {
+		    if c != '%' {
+			expanded.push(c);
+			continue;
+		    }
+		    match chars.next() {
+			Some('%') => expanded.push('%'),
+			Some('c') => expanded.push_str(&entry.name),
+			Some('k') => expanded.push_str(&entry.path.to_string_lossy()),
+			_ => {}, // deprecated or file codes
+		    }
+		}
+		args.push(expanded);
+	    },
+	}
+    }
+    Some(args)
+}
+
+// Arguments are separated by spaces, and may be "quoted", where \" \` \$ and \\ are escapes
+fn split_exec(exec: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+	match c {
+	    ' ' => args.extend(arg.take()),
+	    '"' => {
+		let quoted = arg.get_or_insert_with(String::new);
+		loop {
+		    match chars.next()? {
+			'"' => break,
+			'\\' => quoted.push(chars.next()?),
+			c => quoted.push(c),
+		    }
+		}
+	    },
+	    c => arg.get_or_insert_with(String::new).push(c),
+	}
+    }
+    args.extend(arg);
+    Some(args)
+}
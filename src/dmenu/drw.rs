@@ -1,217 +1,174 @@
-use x11::xlib::{Display, Window, Drawable, GC,
-		XWindowAttributes, XFreeGC,
-		XUngrabKey,
-		XDefaultColormap, XDefaultVisual, False, 
-		XFillRectangle, XSetForeground, 
-		AnyKey,
-		XDrawRectangle, XCopyArea, 
-		XSync, AnyModifier, XCloseDisplay,
-		XFreePixmap};
-use x11::xft::{XftColor, FcPattern, XftDrawStringUtf8,
-	       XftDraw, XftDrawCreate,
-	       XftTextExtentsUtf8, XftCharExists, XftFontMatch, XftDrawDestroy};
-use x11::xrender::XGlyphInfo;
-use fontconfig::fontconfig::{FcPatternAddBool, FcPatternDestroy,
-			     FcCharSetCreate, FcCharSetAddChar, FcPatternDuplicate, FcPatternAddCharSet,
-			     FcCharSetDestroy, FcMatchPattern, FcConfigSubstitute};
-use crate::additional_bindings::fontconfig::{FC_SCALABLE, FC_CHARSET, FC_COLOR, FcTrue, FcFalse};
-use libc::{c_uchar, c_int, c_uint, c_void, free};
-use std::{mem::MaybeUninit, ptr};
+use libc::{c_int, c_uint};
+use std::{collections::{HashMap, VecDeque}, sync::Arc};
 use unicode_segmentation::UnicodeSegmentation;
 use itertools::Itertools;
 
+use crate::backend::{Backend, Color, Event};
 use crate::item::{Items, Direction::*};
 use crate::globals::*;
 use crate::config::*;
-use crate::fnt::*;
+use crate::util::LineReader;
+use crate::history::History;
+use crate::plugin::Plugin;
+use crate::modecmd::ModeCmd;
+use crate::mode::Modes;
+use crate::matching::Matcher;
+use crate::vi::Vi;
 use crate::result::*;
 
+/// A byte range of text drawn with its own [fg, bg]
+pub type Run = (usize, usize, [Color; 2]);
+
 #[derive(PartialEq, Debug)]
 pub enum TextOption<'a> {
     Prompt,
     Input,
     Other(&'a String),
+    Styled(&'a String, Vec<Run>),
 }
 use TextOption::*;
 
 #[derive(Debug)]
 pub struct Drw {
-    pub wa: XWindowAttributes,
-    pub dpy: *mut Display,
-    pub screen: c_int,
-    pub root: Window,
-    pub drawable: Drawable,
-    pub gc: GC,
-    pub scheme: [*mut XftColor; 2],
-    pub fonts: Vec<Fnt>,
+    pub backend: Box<dyn Backend>, // X11 or Wayland, from --backend
+    pub scheme: [Color; 2],
     pub pseudo_globals: PseudoGlobals,
     pub w: c_int,
     pub h: c_int,
     pub config: Config,
     pub input: String,
     pub items: Option<Items>,
+    pub stdin_reader: Option<LineReader>, // Some while stdin is read incrementally
+    pub history: Option<History>,
+    pub color_cache: HashMap<String, Option<Color>>, // colors from --markup
+    pub icons: HashMap<(String, u32), Option<usize>>, // backend image ids, by path and size
+    pub widths: HashMap<Arc<String>, c_int>, // of item texts, see text_width
+    pub plugins: Vec<Box<dyn Plugin>>, // loaded at runtime
+    pub mode_cmd: Option<ModeCmd>, // helper supplying items, from --mode-cmd
+    pub modes: Modes,
+    pub matcher: Box<dyn Matcher>, // from --matching, cycled with Alt-m
+    pub replay: VecDeque<Event>, // scripted keys, handled before the backend's
+    pub vi: Vi, // normal or insert mode, for --vi
 }
 
 impl Drw {
     pub fn fontset_getwidth(&mut self, text: TextOption) -> CompResult<c_int> {
-	if self.fonts.len() == 0 {
-	    Ok(0)
-	} else {
-	    self.text(0, 0, 0, 0, 0, text, false).map(|o| o.0)
-	}
+	self.text(0, 0, 0, 0, 0, text, false).map(|o| o.0)
     }
 
     pub fn text(&mut self, mut x: c_int, y: c_int, mut w: c_uint, h: c_uint, lpad: c_uint, text_opt: TextOption, invert: bool) -> CompResult<(c_int, Option<i32>)> {
-	let mut text: String = {
+	let (mut text, runs): (String, Vec<Run>) = {
 	    match text_opt {
-		Prompt => self.config.prompt.clone(),
-		Input => self.format_input()?,
-		Other(string) => string.to_string(),
+		Prompt => (self.prompt_text(), Vec::new()),
+		Input => (self.format_input()?, Vec::new()),
+		Other(string) => (string.to_string(), Vec::new()),
+		Styled(string, runs) => (string.to_string(), runs),
 	    }
 	};
-	unsafe {
-	    
-	    let render = x>0 || y>0 || w>0 || h>0;
-
-	    if text.len() == 0 || self.fonts.len() == 0 {
-		return Ok((0, None));
-	    }
-	    
-	    let mut d: *mut XftDraw = ptr::null_mut();
-
-	    if !render {
-		w = !0; // maximize w so that underflow never occurs
-	    } else {
-		XSetForeground(self.dpy, self.gc, (*self.scheme[if invert {ColFg} else {ColBg} as usize]).pixel);
-		XFillRectangle(self.dpy, self.drawable, self.gc, x, y, w as u32, h);
-		d = XftDrawCreate(self.dpy, self.drawable,
-		                  XDefaultVisual(self.dpy, self.screen),
-		                  XDefaultColormap(self.dpy, self.screen));
-		x += lpad as c_int;
-		w -= lpad;
-	    }
+	let render = x>0 || y>0 || w>0 || h>0;
 
-	    let mut slice_start = 0;
-	    let mut slice_end = 0;
-	    let mut cur_font: Option<usize> = None;
-	    let mut spool = Spool::new();
+	if text.len() == 0 {
+	    return Ok((0, None));
+	}
 
-	    text.push_str("."); // this will be removed later; turned into elipses
-	    
-	    for cur_char in text.chars() {
-		// String is already utf8 so we don't need to do extra conversions
-		// As such, this logic is changed from the source dmenu quite a bit
+	if !render {
+	    w = !0; // maximize w so that underflow never occurs
+	} else {
+	    self.backend.rect(x, y, w, h, self.scheme[if invert {ColFg} else {ColBg} as usize], true);
+	    x += lpad as c_int;
+	    w -= lpad;
+	}
 
-		let mut found_font = self.fonts.iter().position(|font| XftCharExists(self.dpy, font.xfont, cur_char as u32) == 1);
-		if cur_font.is_some() && cur_font == found_font {
-		    // append to list to be printed
-		    slice_end += cur_char.len_utf8();
-		}
-		if cur_font.is_none() || cur_font != found_font {
-		    if found_font.is_none() {
-			
-			// char is not found in any fonts
-			// In this case, pretend it's in the first font, as it must be drawn
-			
-			let fccharset = FcCharSetCreate();
-			FcCharSetAddChar(fccharset, cur_char as u32);
-			if self.fonts[0].pattern_pointer == ptr::null_mut() {
-			    /* Refer to the comment in xfont_create for more information. */
-			    return Die::stderr("fonts must be loaded from font strings".to_owned());
-			}
-			
-			let fcpattern = FcPatternDuplicate(self.fonts[0].pattern_pointer as *const c_void);
-			FcPatternAddCharSet(fcpattern as *mut c_void, FC_CHARSET, fccharset);
-			FcPatternAddBool(fcpattern as *mut c_void, FC_SCALABLE, FcTrue);
-			FcPatternAddBool(fcpattern as *mut c_void, FC_COLOR, FcFalse);
+	let mut slice_start = 0;
+	let mut cur_colors: Option<Option<[Color; 2]>> = None;
+	let mut spool = Spool::new();
 
-			FcConfigSubstitute(ptr::null_mut(), fcpattern as *mut c_void, FcMatchPattern);
-			let mut result: x11::xft::FcResult = x11::xft::FcResult::NoId; // XftFontMatch isn't null safe so we need some memory (result is actually discarded)
-			let font_match = XftFontMatch(self.dpy, self.screen, fcpattern as *const FcPattern, &mut result);
+	text.push_str("."); // this will be removed later; turned into elipses
 
-			FcCharSetDestroy(fccharset);
-			FcPatternDestroy(fcpattern);
-			
-			if font_match != ptr::null_mut() {
-			    let mut usedfont = Fnt::new(self, None, font_match)?;
-			    
-			    if XftCharExists(self.dpy, usedfont.xfont, cur_char as u32) != 0 {
-				found_font = Some(self.fonts.len());
-				self.fonts.push(usedfont);
-			    } else {
-				usedfont.free(self.dpy);
-				found_font = Some(0);
-			    }
-			}
-		    }
-		    // Need to switch fonts
-		    // First, take care of the stuff pending print
-		    if cur_font.is_some() {
-			spool.push((String::from_utf8_unchecked(text.as_bytes()
-								[slice_start..slice_end]
-								.to_vec()),
-				    cur_font));
-		    }
-		    // Then, set up next thing to print
-		    cur_font = found_font;
-		    slice_start = slice_end;
-		    slice_end += cur_char.len_utf8();
+	// The backend takes care of fonts, so text is only split where colors change
+	for (i, _) in text.char_indices() {
+	    let colors = runs.iter().find(|(start, end, _)| i >= *start && i < *end)
+		.map(|(_, _, colors)| *colors);
+	    if let Some(cur) = cur_colors {
+		if cur != colors {
+		    spool.push((text[slice_start..i].to_string(), cur));
+		    slice_start = i;
 		}
 	    }
-	    // take care of the remaining slice, if it exists
-	    spool.push((String::from_utf8_unchecked(text.as_bytes()
-						    [slice_start..slice_end]
-						    .to_vec()),
-			cur_font));
+	    cur_colors = Some(colors);
+	}
+	// take care of the remaining slice
+	spool.push((text[slice_start..].to_string(), cur_colors.unwrap()));
 
-	    let padded_width = w - self.pseudo_globals.lrpad as u32/2;
-	    spool.elipsate(&self, padded_width);
-	    while render && spool.width(&self) > padded_width {
-		spool.elipse_pop();
-	    }
-	    
-	    let elip_width = spool.elip_width(&self);
-	    for (slice, font) in spool.into_iter() {
-		// Do early truncation (...)
-		self.render(&mut x, &y, &mut w, &h,
-			    slice, &font, d, render, invert);
-	    }
-	    
-	    if d != ptr::null_mut() {
-		XftDrawDestroy(d);
-	    }
+	let padded_width = w - self.pseudo_globals.lrpad as u32/2;
+	spool.elipsate(self.backend.as_mut(), padded_width)?;
+	while render && spool.width(self.backend.as_mut())? > padded_width {
+	    spool.elipse_pop();
+	}
 
-	    Ok((x + if render {w} else {0} as i32, elip_width))
+	let elip_width = spool.elip_width(self.backend.as_mut())?;
+	for (slice, colors) in spool.into_iter() {
+	    self.render(&mut x, &y, &mut w, &h, slice, colors, render, invert)?;
 	}
+
+	Ok((x + if render {w} else {0} as i32, elip_width))
     }
 
-    fn render(&self, x: &mut i32, y: &i32, w: &mut u32, h: &u32, text: String, cur_font: &Option<usize>, d: *mut XftDraw, render: bool, invert: bool) {
+    fn render(&mut self, x: &mut i32, y: &i32, w: &mut u32, h: &u32, text: String, colors: Option<[Color; 2]>, render: bool, invert: bool) -> CompResult<()> {
 	if text.len() == 0 {
-	    return;
+	    return Ok(());
 	}
-	unsafe {
-	    let usedfont = cur_font.map(|i| &self.fonts[i]).unwrap();
-	    let font_ref = usedfont;
-	    let (substr_width, _) = self.font_getexts(font_ref, text.as_ptr() as *mut c_uchar, text.len() as c_int);
-	    if render {
-		let ty = *y + (*h as i32 - usedfont.height as i32) / 2 + (*usedfont.xfont).ascent;	
-		XftDrawStringUtf8(d, self.scheme[if invert {ColBg} else {ColFg} as usize],  self.fonts[cur_font.unwrap()].xfont, *x, ty, text.as_ptr() as *mut c_uchar, text.len() as c_int);
+	let substr_width = self.backend.text_width(&text)?;
+	if render {
+	    let scheme = colors.unwrap_or(self.scheme);
+	    if colors.is_some() { // runs bring their own background
+		self.backend.rect(*x, *y, substr_width, *h, scheme[if invert {ColFg} else {ColBg} as usize], true);
 	    }
-	    *x += substr_width as i32;
-	    *w -= substr_width;
+	    self.backend.text(*x, *y, *h, &text, scheme[if invert {ColBg} else {ColFg} as usize])?;
 	}
+	*x += substr_width as i32;
+	*w -= substr_width;
+	Ok(())
     }
 
-    pub fn font_getexts(&self, font: &Fnt, subtext: *const c_uchar, len: c_int) -> (c_uint, c_uint) {
-	unsafe { //                                                                (width,  height)
-	    let mut ext: XGlyphInfo = MaybeUninit::uninit().assume_init();
-	    XftTextExtentsUtf8(self.dpy, font.xfont, subtext, len, &mut ext);
-	    (ext.xOff as c_uint, font.height) // (width, height)
+    /// The prompt, along with any status that's shown next to it
+    pub fn prompt_text(&self) -> String {
+	let mut prompt = match self.mode_cmd.as_ref().and_then(|cmd| cmd.prompt.as_ref()) {
+	    Some(prompt) => prompt.clone(),
+	    None => self.config.prompt.clone(),
+	};
+	if let Some(name) = self.modes.active_name() {
+	    prompt = match self.modes.active_prompt() {
+		Some(own) => format!("{} {}", name, own),
+		None if prompt.len() > 0 => format!("{} {}", name, prompt),
+		None => name.to_string(),
+	    };
+	}
+	let matcher = self.matcher.name();
+	if matcher != "tokens" && matcher != ConfigDefault::matching() { // fuzzy with its plugin
+	    if prompt.len() > 0 {
+		prompt.push(' ');
+	    }
+	    prompt.push_str(&format!("[{}]", matcher));
 	}
+	if self.config.vi || self.vi.normal {
+	    if prompt.len() > 0 {
+		prompt.push(' ');
+	    }
+	    prompt.push_str(if self.vi.normal {"[normal]"} else {"[insert]"});
+	}
+	if self.stdin_reader.is_some() {
+	    if prompt.len() > 0 {
+		prompt.push(' ');
+	    }
+	    prompt.push_str(&format!("[{}…]", self.items.as_ref().map(|i| i.data.len()).unwrap_or(0)));
+	}
+	prompt
     }
-    
+
     pub fn draw(&mut self) -> CompResult<()> { // drawmenu
-	self.pseudo_globals.promptw = if self.config.prompt.len() != 0 {
+	let has_prompt = self.prompt_text().len() > 0;
+	self.pseudo_globals.promptw = if has_prompt {
 	    self.textw(Prompt)?
 	} else {
 	    0
@@ -222,7 +179,7 @@ impl Drw {
 
 	let mut x = 0;
 	
-	if self.config.prompt.len() > 0 { // draw prompt
+	if has_prompt { // draw prompt
 	    self.setscheme(SchemeSel);
 	    x = self.text(x, 0, self.pseudo_globals.promptw as c_uint,
 			    self.pseudo_globals.bh as u32, self.pseudo_globals.lrpad as u32 / 2, Prompt, false)?.0;
@@ -253,63 +210,40 @@ impl Drw {
 
 	if curpos < truncated.unwrap_or(w - self.pseudo_globals.lrpad/2) {
 	    self.setscheme(SchemeNorm);
-	    let tallest_font = self.fonts.iter().map(|f| f.height).max().unwrap();
+	    let tallest_font = self.backend.max_font_height();
 	    self.rect(x + curpos, (self.pseudo_globals.bh - tallest_font) as i32 / 2 + 2, 2, tallest_font - 4, true, false);
 	}
 
-	self.map(self.pseudo_globals.win, 0, 0, self.w, self.h);
+	self.backend.map(0, 0, self.w, self.h);
 	Ok(())
     }
-    
-    pub fn map(&self, win: Window, x: c_int, y: c_int, w: c_int, h: c_int) {
-	unsafe {
-	    XCopyArea(self.dpy, self.drawable, win, self.gc, x, y, w as u32, h as u32, x, y);
-	    XSync(self.dpy, False);
-	}
-    }
 
     pub fn textw(&mut self, text: TextOption) -> CompResult<c_int> {
 	self.fontset_getwidth(text).map(|computed_width| computed_width + self.pseudo_globals.lrpad)
     }
+
+    /// textw of an item's text, remembered since the same items are laid out over and over
+    pub fn text_width(&mut self, text: &Arc<String>) -> CompResult<c_int> {
+	if let Some(width) = self.widths.get(text) {
+	    return Ok(*width);
+	}
+	let width = self.textw(Other(text))?;
+	self.widths.insert(text.clone(), width);
+	Ok(width)
+    }
     
     pub fn setscheme(&mut self, scm: Schemes) {
 	self.scheme = self.pseudo_globals.schemeset[scm as usize];
     }
 
-    fn rect(&self, x: c_int, y: c_int, w: c_uint, h: c_uint, filled: bool, invert: bool) {
-	unsafe {
-	    XSetForeground(self.dpy, self.gc, (*self.scheme[if invert {ColBg} else {ColFg} as usize]).pixel);
-	    if filled {
-		XFillRectangle(self.dpy, self.drawable, self.gc, x, y, w, h);
-	    } else {
-		XDrawRectangle(self.dpy, self.drawable, self.gc, x, y, w - 1, h - 1);
-	    }
-	}
-    }
-}
-
-impl Drop for Drw {
-    fn drop(&mut self) {
-	unsafe {
-	    for font in &mut self.fonts {
-		font.free(self.dpy);
-	    }
-	    XUngrabKey(self.dpy, AnyKey, AnyModifier, self.root);
-	    for i in 0..SchemeLast as usize{
-		free(self.pseudo_globals.schemeset[i][0] as *mut c_void);
-		free(self.pseudo_globals.schemeset[i][1] as *mut c_void);
-	    }
-	    XFreePixmap(self.dpy, self.drawable);
-	    XFreeGC(self.dpy, self.gc);
-	    XSync(self.dpy, False);
-	    XCloseDisplay(self.dpy);
-	}
+    pub fn rect(&mut self, x: c_int, y: c_int, w: c_uint, h: c_uint, filled: bool, invert: bool) {
+	self.backend.rect(x, y, w, h, self.scheme[if invert {ColBg} else {ColFg} as usize], filled);
     }
 }
 
-// Utility struct; contains chars and fonts
+// Utility struct; contains chars and colors
 struct Spool {
-    data: Vec<(String, Option<usize>)>,
+    data: Vec<(String, Option<[Color; 2]>)>,
     elipsed: bool,
 }
 
@@ -317,24 +251,27 @@ impl Spool {
     pub fn new() -> Self {
 	Self{data: Vec::new(), elipsed: false}
     }
-    pub fn width(&self, drw: &Drw) -> u32 {
-	self.data.iter().map(
-	    |(slice, font)|
-	    drw.font_getexts(&drw.fonts[font.unwrap()],
-			     slice.as_ptr() as *mut c_uchar,
-			     slice.len() as c_int).0)
-	    .fold(0, |sum, i| sum + i)
+    fn measure<'a>(slices: impl Iterator<Item = &'a (String, Option<[Color; 2]>)>, backend: &mut dyn Backend) -> CompResult<u32> {
+	let mut sum = 0;
+	for (slice, _) in slices {
+	    sum += backend.text_width(slice)?;
+	}
+	Ok(sum)
+    }
+    pub fn width(&self, backend: &mut dyn Backend) -> CompResult<u32> {
+	Self::measure(self.data.iter(), backend)
     }
-    pub fn elipsate(&mut self, drw: &Drw, w: u32) {
+    pub fn elipsate(&mut self, backend: &mut dyn Backend, w: u32) -> CompResult<()> {
 	let elipse = self.pop();
-	if self.width(drw) > w {
+	if self.width(backend)? > w {
 	    self.elipsed = true;
 	    self.push(elipse.clone());
 	    self.push(elipse.clone());
 	    self.push(elipse);
 	}
+	Ok(())
     }
-    fn pop(&mut self)  -> (String, Option<usize>){
+    fn pop(&mut self)  -> (String, Option<[Color; 2]>){
 	let len = self.data.len();
 	if self.data[len-1].0.len() == 1 {
 	    self.data.pop().unwrap()
@@ -356,26 +293,21 @@ impl Spool {
 	    }
 	}
     }
-    pub fn push(&mut self, arg: (String, Option<usize>)) {
+    pub fn push(&mut self, arg: (String, Option<[Color; 2]>)) {
 	self.data.push(arg);
     }
-    pub fn into_iter(self) -> std::vec::IntoIter<(String, Option<usize>)> {
+    pub fn into_iter(self) -> std::vec::IntoIter<(String, Option<[Color; 2]>)> {
 	self.data.into_iter()
     }
-    pub fn elip_width(&self, drw: &Drw) -> Option<i32> {
+    pub fn elip_width(&self, backend: &mut dyn Backend) -> CompResult<Option<i32>> {
 	if !self.elipsed {
-	    None
+	    Ok(None)
 	} else {
-	    Some(if self.data.len() <= 3 {
-		self.width(drw)
+	    Ok(Some(if self.data.len() <= 3 {
+		self.width(backend)?
 	    } else {
-		self.data.iter().rev().skip(3).map(
-		    |(slice, font)|
-		    drw.font_getexts(&drw.fonts[font.unwrap()],
-				     slice.as_ptr() as *mut c_uchar,
-				     slice.len() as c_int).0)
-		    .fold(0, |sum, i| sum + i)
-	    } as i32)
+		Self::measure(self.data.iter().rev().skip(3), backend)?
+	    } as i32))
 	}
     }
 }
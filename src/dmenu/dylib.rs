@@ -0,0 +1,66 @@
+use std::ffi::{CStr, CString};
+use std::path::PathBuf;
+use libc::c_char;
+use libloading::Library;
+use serde_json::Value;
+
+use crate::plugin::Plugin;
+use crate::result::*;
+
+// char *dmenu_plugin_call(const char *request);
+type CallFn = unsafe extern "C" fn(*const c_char) -> *mut c_char;
+// void dmenu_plugin_free(char *response);
+type FreeFn = unsafe extern "C" fn(*mut c_char);
+
+/// A plugin in a shared library, exporting dmenu_plugin_call and dmenu_plugin_free
+/// Requests and responses are NUL terminated JSON. A NULL response is the same as null.
+#[derive(Debug)]
+pub struct DylibPlugin {
+    name: String,
+    call: CallFn,
+    free: FreeFn,
+    _library: Library, // must outlive call and free
+}
+
+impl DylibPlugin {
+    /// Opens NAME as libNAME.so from search_path, or a path if NAME contains a '/'
+    pub fn open(name: &str, search_path: &[PathBuf]) -> CompResult<Self> {
+	let path = if name.contains('/') {
+	    Some(PathBuf::from(name))
+	} else {
+	    search_path.iter().map(|dir| dir.join(format!("lib{}.so", name)))
+		.find(|path| path.exists())
+	}.ok_or_else(|| Die::Stderr(format!("--plugin: could not find plugin '{}'", name)))?;
+	let name = path.file_stem()
+	    .map(|stem| stem.to_string_lossy())
+	    .map(|stem| stem.strip_prefix("lib").unwrap_or(&stem).to_string())
+	    .unwrap_or_else(|| name.to_string());
+	let err = |err: libloading::Error| Die::Stderr(format!("plugin {}: {}", name, err));
+	unsafe {
+	    let library = Library::new(&path).map_err(err)?;
+	    let call = *library.get::<CallFn>(b"dmenu_plugin_call\0").map_err(err)?;
+	    let free = *library.get::<FreeFn>(b"dmenu_plugin_free\0").map_err(err)?;
+	    Ok(Self{name: name.clone(), call, free, _library: library})
+	}
+    }
+}
+
+impl Plugin for DylibPlugin {
+    fn name(&self) -> &str {
+	&self.name
+    }
+
+    fn call(&mut self, request: Value) -> CompResult<Value> {
+	let request = CString::new(request.to_string()) // JSON escapes NULs
+	    .map_err(|_| Die::Stderr(format!("plugin {}: invalid request", self.name)))?;
+	unsafe {
+	    let response = (self.call)(request.as_ptr());
+	    if response.is_null() {
+		return Ok(Value::Null);
+	    }
+	    let parsed = serde_json::from_slice(CStr::from_ptr(response).to_bytes());
+	    (self.free)(response);
+	    parsed.map_err(|err| Die::Stderr(format!("plugin {}: {}", self.name, err)))
+	}
+    }
+}
@@ -0,0 +1,94 @@
+use libc::{access, X_OK};
+use std::collections::BTreeSet;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::drw::Drw;
+use crate::item::Item;
+use crate::mode::Mode;
+use crate::util::xdg_dir;
+use crate::result::*;
+
+/// Executables in $PATH, run with $SHELL when selected, see --run
+/// As with dmenu_run, Shift-Return runs the input, so arguments can be given.
+#[derive(Debug)]
+pub struct Executables;
+
+impl Mode for Executables {
+    fn name(&self) -> &str {
+	"run"
+    }
+    fn items(&mut self, _drw: &mut Drw) -> CompResult<Vec<Item>> {
+	Ok(list().into_iter().map(|name| Item::new(name, false)).collect())
+    }
+    fn dispose(&mut self, _drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	let shell = env::var("SHELL").ok().filter(|s| s.len() > 0)
+	    .unwrap_or_else(|| "/bin/sh".to_string());
+	Command::new(&shell).arg("-c").arg(&output).spawn()
+	    .map_err(|err| Die::Stderr(format!("Could not run {}: {}", shell, err)))?;
+	Ok(recommendation)
+    }
+}
+
+/// Names of the executables in $PATH, sorted and without duplicates, like dmenu_path.
+/// They're cached in $XDG_CACHE_HOME/dmenu_run, which is rebuilt
+/// once any directory in $PATH has been modified since it was written.
+pub fn list() -> Vec<String> {
+    let dirs: Vec<PathBuf> = env::var_os("PATH")
+	.map(|path| env::split_paths(&path).collect()).unwrap_or_default();
+    let cache = xdg_dir("XDG_CACHE_HOME", ".cache").map(|dir| dir.join("dmenu_run"));
+    if let Some(cache) = cache.as_ref() {
+	if !stale(cache, &dirs) {
+	    if let Ok(contents) = fs::read_to_string(cache) {
+		return contents.lines().map(|name| name.to_string()).collect();
+	    }
+	}
+    }
+    let mut names = BTreeSet::new();
+    for dir in &dirs {
+	let entries = match fs::read_dir(dir) {
+	    Ok(entries) => entries,
+	    Err(_) => continue,
+	};
+	for entry in entries.filter_map(|entry| entry.ok()) {
+	    let name = entry.file_name().to_string_lossy().into_owned();
+	    if !name.starts_with('.') && executable(&entry.path()) {
+		names.insert(name);
+	    }
+	}
+    }
+    let names: Vec<String> = names.into_iter().collect();
+    if let Some(cache) = cache {
+	let written = fs::create_dir_all(cache.parent().unwrap())
+	    .and_then(|_| fs::write(&cache, names.iter().map(|name| format!("{}\n", name)).collect::<String>()));
+	if let Err(err) = written {
+	    eprintln!("Could not write {}: {}", cache.display(), err);
+	}
+    }
+    names
+}
+
+// Missing, or older than one of dirs
+fn stale(cache: &Path, dirs: &[PathBuf]) -> bool {
+    let written = match fs::metadata(cache).and_then(|meta| meta.modified()) {
+	Ok(written) => written,
+	Err(_) => return true,
+    };
+    dirs.iter().any(|dir| fs::metadata(dir).and_then(|meta| meta.modified())
+		    .map(|modified| modified > written).unwrap_or(false))
+}
+
+// A regular file (after following links) the user may execute
+fn executable(path: &Path) -> bool {
+    if !path.is_file() {
+	return false;
+    }
+    match CString::new(path.as_os_str().as_bytes()) {
+	Ok(path) => unsafe{access(path.as_ptr(), X_OK) == 0},
+	Err(_) => false,
+    }
+}
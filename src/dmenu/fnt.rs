@@ -8,10 +8,9 @@ use fontconfig::fontconfig::{FcPatternDestroy, FcResultMatch, FcPatternGetBool,
 use crate::additional_bindings::fontconfig::{FC_COLOR, FC_FAMILY};
 use std::ptr;
 use std::ffi::c_void;
-use libc::c_uint;
+use libc::{c_int, c_uint};
 use std::mem::MaybeUninit;
 
-use crate::drw::Drw;
 use crate::result::*;
 
 #[derive(Debug)]
@@ -29,7 +28,7 @@ impl PartialEq for Fnt {
 
 impl Fnt {
     // xfont_create
-    pub fn new(drw: &Drw, fontopt: Option<&String>, mut pattern: *mut FcPattern) -> CompResult<Self> {
+    pub fn new(dpy: *mut Display, screen: c_int, fontopt: Option<&String>, mut pattern: *mut FcPattern) -> CompResult<Self> {
 	let __blank = "".to_owned(); // fighting the borrow checker
 	let fontname = fontopt.unwrap_or(&__blank);
 	let fontptr = if fontname.len() > 0 {
@@ -50,19 +49,19 @@ impl Fnt {
 		 * FcNameParse; using the latter results in the desired fallback
 		 * behaviour whereas the former just results in missing-character
 		 * rectangles being drawn, at least with some fonts. */
-		xfont = XftFontOpenName(drw.dpy, drw.screen, fontptr);
+		xfont = XftFontOpenName(dpy, screen, fontptr);
 		if xfont == ptr::null_mut() {
 		    return Die::stderr(format!("error, cannot load font from name: '{}'", fontname));
 		}
 		
 		pattern = XftNameParse(fontptr);
 		if pattern == ptr::null_mut() {
-		    XftFontClose(drw.dpy, xfont);
+		    XftFontClose(dpy, xfont);
 		    return Die::stderr(format!("error, cannot parse font name to pattern: '{}'",
 				       fontname));
 		}
 	    } else if pattern != ptr::null_mut() {
-		xfont = XftFontOpenPattern(drw.dpy, pattern);
+		xfont = XftFontOpenPattern(dpy, pattern);
 		if xfont == ptr::null_mut() {
 		    return Die::stderr(format!("error, cannot load font '{}' from pattern.",
 				       fontname));
@@ -82,7 +81,7 @@ impl Fnt {
 	    let mut iscol: FcBool = MaybeUninit::uninit().assume_init();
 	    if FcPatternGetBool((*xfont).pattern as *mut c_void, FC_COLOR, 0, &mut iscol) == FcResultMatch
 		&& iscol != 0 {
-		XftFontClose(drw.dpy, xfont);
+		XftFontClose(dpy, xfont);
 		return Die::stderr("Cannot load color fonts".to_owned());
 	    }
 
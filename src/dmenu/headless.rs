@@ -0,0 +1,116 @@
+use libc::{c_int, c_uint};
+use png::{BitDepth, ColorType, Encoder};
+use std::{any::Any, fs::File, io::BufWriter};
+
+use crate::backend::{Backend, Color, Event};
+use crate::canvas::Canvas;
+use crate::config::Config;
+use crate::result::*;
+
+/// How wide --render-to images are, there's no screen to take it from
+pub const WIDTH: c_int = 1280;
+
+/// Draws on a Canvas with no display at all, for --render-to.
+/// Every frame is written to the file as a PNG, and the menu closes after the first.
+#[derive(Debug)]
+pub struct Headless {
+    canvas: Canvas,
+    path: String,
+    error: Option<String>, // from writing the image, reported by next_event
+}
+
+impl Headless {
+    pub fn open(config: &Config) -> CompResult<Self> {
+	Ok(Self{canvas: Canvas::default(), path: config.render_to.clone().unwrap_or_default(), error: None})
+    }
+
+    fn write(&self) -> Result<(), String> {
+	let file = File::create(&self.path).map_err(|err| err.to_string())?;
+	let mut encoder = Encoder::new(BufWriter::new(file), self.canvas.w as u32, self.canvas.h as u32);
+	encoder.set_color(ColorType::RGBA);
+	encoder.set_depth(BitDepth::Eight);
+	let data: Vec<u8> = self.canvas.pixels.iter()
+	    .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8, (p >> 24) as u8])
+	    .collect();
+	encoder.write_header().and_then(|mut writer| writer.write_image_data(&data))
+	    .map_err(|err| err.to_string())
+    }
+}
+
+impl Backend for Headless {
+    fn load_fonts(&mut self, names: &[String]) -> CompResult<()> {
+	self.canvas.load_fonts(names)
+    }
+
+    fn font_height(&self) -> c_uint {
+	self.canvas.font_height()
+    }
+
+    fn max_font_height(&self) -> c_uint {
+	self.canvas.max_font_height()
+    }
+
+    fn color(&mut self, name: &str) -> Option<Color> {
+	Color::parse(name)
+    }
+
+    fn text_width(&mut self, text: &str) -> CompResult<c_uint> {
+	Ok(self.canvas.text_width(text))
+    }
+
+    fn text(&mut self, x: c_int, y: c_int, h: c_uint, text: &str, color: Color) -> CompResult<()> {
+	self.canvas.text(x, y, h, text, color);
+	Ok(())
+    }
+
+    fn rect(&mut self, x: c_int, y: c_int, w: c_uint, h: c_uint, color: Color, filled: bool) {
+	self.canvas.rect(x, y, w, h, color, filled);
+    }
+
+    fn load_image(&mut self, pixels: Vec<u32>, size: c_uint) -> usize {
+	self.canvas.load_image(pixels, size)
+    }
+
+    fn draw_image(&mut self, id: usize, x: c_int, y: c_int) {
+	self.canvas.draw_image(id, x, y);
+    }
+
+    fn grab_keyboard(&mut self, _config: &Config) -> CompResult<()> {
+	Ok(())
+    }
+
+    fn create_window(&mut self, h: c_int, bg: Color, _config: &Config) -> CompResult<c_int> {
+	self.canvas.resize(WIDTH as usize, h as usize);
+	self.canvas.rect(0, 0, WIDTH as c_uint, h as c_uint, bg, true);
+	Ok(WIDTH)
+    }
+
+    fn map(&mut self, _x: c_int, _y: c_int, _w: c_int, _h: c_int) {
+	if let Err(err) = self.write() {
+	    self.error = Some(format!("--render-to: cannot write {}: {}", self.path, err));
+	}
+    }
+
+    fn fd(&self) -> c_int {
+	-1 // never polled, as there's always an event
+    }
+
+    fn pending(&mut self) -> bool {
+	true
+    }
+
+    fn next_event(&mut self) -> CompResult<Option<Event>> {
+	match self.error.take() {
+	    Some(err) => Die::stderr(err),
+	    None => Ok(Some(Event::Close)),
+	}
+    }
+
+    fn clipboard(&mut self) -> CompResult<String> {
+	Ok(String::new())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+	self
+    }
+}
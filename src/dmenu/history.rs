@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use itertools::Itertools;
+
+use crate::item::Item;
+use crate::util::xdg_dir;
+use crate::result::*;
+
+#[derive(Debug)]
+struct Entry {
+    count: u32,
+    last: u64, // seconds since epoch
+}
+
+const MAX_INPUTS: usize = 100;
+
+/// Selections made under a --history-key, kept in
+/// $XDG_STATE_HOME/dmenu-rs/KEY.history as `count<TAB>last used<TAB>text` lines
+/// Submitted input is kept seperately in KEY.inputs, oldest first
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+    inputs_path: PathBuf,
+    inputs: Vec<String>,
+    position: Option<usize>, // index into inputs while browsing
+    draft: String, // what was typed before browsing started
+}
+
+impl History {
+    pub fn load(key: &str) -> CompResult<Self> {
+	if key.len() == 0 || key.contains('/') {
+	    return Die::stderr("--history-key: key must be a non-empty file name".to_owned());
+	}
+	let dir = xdg_dir("XDG_STATE_HOME", ".local/state")
+	    .ok_or(Die::Stderr("Could not find a directory for history".to_owned()))?
+	    .join("dmenu-rs");
+	let path = dir.join(format!("{}.history", key));
+	let inputs_path = dir.join(format!("{}.inputs", key));
+	let mut entries = HashMap::new();
+	if let Ok(contents) = fs::read_to_string(&path) {
+	    for line in contents.lines() {
+		let mut fields = line.splitn(3, '\t');
+		if let (Some(count), Some(last), Some(text)) = (fields.next(), fields.next(), fields.next()) {
+		    if let (Ok(count), Ok(last)) = (count.parse(), last.parse()) {
+			entries.insert(text.to_string(), Entry{count, last});
+		    }
+		}
+	    }
+	}
+	let inputs = fs::read_to_string(&inputs_path)
+	    .map(|contents| contents.lines().map(|l| l.to_string()).collect())
+	    .unwrap_or_default();
+	Ok(Self{path, entries, inputs_path, inputs, position: None, draft: String::new()})
+    }
+
+    /// Counts a selection and writes the history back to disk
+    pub fn record(&mut self, text: &str) -> CompResult<()> {
+	let entry = self.entries.entry(text.to_string())
+	    .or_insert(Entry{count: 0, last: 0});
+	entry.count += 1;
+	entry.last = now();
+	self.save()
+    }
+
+    /// Remembers submitted input. Repeats are moved up to be the newest entry
+    pub fn record_input(&mut self, input: &str) -> CompResult<()> {
+	self.position = None;
+	if input.len() == 0 || input.contains('\n') {
+	    return Ok(());
+	}
+	self.inputs.retain(|i| i != input);
+	self.inputs.push(input.to_string());
+	if self.inputs.len() > MAX_INPUTS {
+	    self.inputs.drain(..self.inputs.len()-MAX_INPUTS);
+	}
+	let contents = self.inputs.iter().map(|i| format!("{}\n", i)).join("");
+	write(&self.inputs_path, contents)
+    }
+
+    /// Steps back through submitted input
+    /// current is stashed when browsing starts so it can be restored later
+    pub fn older_input(&mut self, current: &str) -> Option<String> {
+	let position = match self.position {
+	    None if self.inputs.len() > 0 => {
+		self.draft = current.to_string();
+		self.inputs.len()-1
+	    },
+	    Some(position) if position > 0 => position-1,
+	    _ => return None,
+	};
+	self.position = Some(position);
+	Some(self.inputs[position].clone())
+    }
+
+    /// Steps forward through submitted input, ending on whatever was being typed
+    pub fn newer_input(&mut self) -> Option<String> {
+	match self.position {
+	    Some(position) if position+1 < self.inputs.len() => {
+		self.position = Some(position+1);
+		Some(self.inputs[position+1].clone())
+	    },
+	    Some(_) => {
+		self.position = None;
+		Some(std::mem::take(&mut self.draft))
+	    },
+	    None => None,
+	}
+    }
+
+    /// Frequency weighted by recency. Zero for things never selected
+    pub fn frecency(&self, text: &str, now: u64) -> f64 {
+	match self.entries.get(text) {
+	    Some(entry) => {
+		let age = now.saturating_sub(entry.last);
+		entry.count as f64 * if age < 60*60 {
+		    4.0
+		} else if age < 60*60*24 {
+		    2.0
+		} else if age < 60*60*24*7 {
+		    0.5
+		} else {
+		    0.25
+		}
+	    },
+	    None => 0.0,
+	}
+    }
+
+    /// Moves frequently and recently used items to the front
+    /// Otherwise, the incoming order is kept
+    pub fn rank(&self, items: Vec<Item>) -> Vec<Item> {
+	if self.entries.len() == 0 {
+	    return items;
+	}
+	let now = now();
+	let mut scored: Vec<(f64, Item)> = items.into_iter()
+	    .map(|item| (self.frecency(&item.text, now), item)).collect();
+	scored.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap()); // stable
+	scored.into_iter().map(|(_, item)| item).collect()
+    }
+
+    fn save(&self) -> CompResult<()> {
+	let contents = self.entries.iter()
+	    .map(|(text, entry)| format!("{}\t{}\t{}\n", entry.count, entry.last, text))
+	    .join("");
+	write(&self.path, contents)
+    }
+}
+
+// Replaces path with contents in one go, so concurrent menus never see half a file.
+// Each file and process writes its own temporary file, so they can't rename each other's.
+fn write(path: &PathBuf, contents: String) -> CompResult<()> {
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(format!(".{}.tmp", process::id()));
+    path.parent().map(fs::create_dir_all).transpose()
+	.and_then(|_| fs::write(&tmp, contents))
+	.and_then(|_| fs::rename(&tmp, path))
+	.map_err(|err| Die::Stderr(format!("Could not write history to {}: {}",
+					   path.display(), err)))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
@@ -0,0 +1,171 @@
+use libc::c_int;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use png::{ColorType, Transformations};
+
+use crate::drw::Drw;
+
+// Decoded pixels, straight (not premultiplied) RGBA
+struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<[u8; 4]>,
+}
+
+impl Drw {
+    /// Icons are square, as tall as the primary font
+    pub fn icon_size(&self) -> u32 {
+	self.backend.font_height()
+    }
+
+    /// Horizontal space an icon takes up, including the gap before the text
+    pub fn icon_width(&self) -> c_int {
+	self.icon_size() as c_int + self.pseudo_globals.lrpad/2
+    }
+
+    /// Composites the icon at path with its top left corner at x, y
+    /// Icons are loaded on first use and cached by path and size.
+    /// Anything that can't be loaded is skipped.
+    pub fn draw_icon(&mut self, path: &str, x: c_int, y: c_int) {
+	let size = self.icon_size();
+	let key = (path.to_string(), size);
+	if !self.icons.contains_key(&key) {
+	    let icon = self.load_icon(path, size);
+	    self.icons.insert(key.clone(), icon);
+	}
+	if let Some(id) = self.icons[&key] {
+	    self.backend.draw_image(id, x, y);
+	}
+    }
+
+    fn load_icon(&mut self, path: &str, size: u32) -> Option<usize> {
+	let image = if path.ends_with(".xpm") {
+	    self.load_xpm(path)
+	} else {
+	    load_png(path)
+	};
+	match image {
+	    Some(image) if image.width > 0 && image.height > 0
+		&& image.data.len() == image.width*image.height => {
+		    Some(self.backend.load_image(scale(&image, size as usize), size))
+		},
+	    _ => {
+		eprintln!("Could not load icon {}", path);
+		None
+	    },
+	}
+    }
+
+    // Colors are resolved by the backend, so names work where it knows them
+    fn load_xpm(&mut self, path: &str) -> Option<Image> {
+	let contents = fs::read_to_string(path).ok()?;
+	let strings = c_strings(&contents);
+	let header: Vec<usize> = strings.get(0)?.split_whitespace()
+	    .map(|n| n.parse().ok()).collect::<Option<_>>()?;
+	let (width, height, ncolors, cpp) = match header[..] {
+	    [width, height, ncolors, cpp, ..] => (width, height, ncolors, cpp),
+	    _ => return None,
+	};
+	let mut palette = HashMap::new();
+	for line in strings.get(1..1+ncolors)? {
+	    let key = line.get(..cpp)?;
+	    let mut words = line[cpp..].split_whitespace();
+	    let mut color = None;
+	    while let (Some(kind), Some(value)) = (words.next(), words.next()) {
+		if kind == "c" {
+		    color = Some(value);
+		}
+	    }
+	    let pixel = match color? {
+		none if none.eq_ignore_ascii_case("none") => [0; 4],
+		name => {
+		    let c = self.color(name)?;
+		    [c.r, c.g, c.b, 255]
+		},
+	    };
+	    palette.insert(key, pixel);
+	}
+	let mut data = Vec::with_capacity(width*height);
+	for row in strings.get(1+ncolors..1+ncolors+height)? {
+	    for x in 0..width {
+		data.push(*palette.get(row.get(x*cpp..(x+1)*cpp)?)?);
+	    }
+	}
+	Some(Image{width, height, data})
+    }
+}
+
+fn load_png(path: &str) -> Option<Image> {
+    let mut decoder = png::Decoder::new(File::open(path).ok()?);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info().ok()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).ok()?;
+    let data = match reader.output_color_type().0 {
+	ColorType::RGBA => buf.chunks(4).map(|p| [p[0], p[1], p[2], p[3]]).collect(),
+	ColorType::RGB => buf.chunks(3).map(|p| [p[0], p[1], p[2], 255]).collect(),
+	ColorType::GrayscaleAlpha => buf.chunks(2).map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+	ColorType::Grayscale => buf.iter().map(|p| [*p, *p, *p, 255]).collect(),
+	ColorType::Indexed => return None, // expanded by the decoder
+    };
+    Some(Image{width: info.width as usize, height: info.height as usize, data})
+}
+
+// The contents of every "string" outside of /* comments */
+fn c_strings(source: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut rest = source;
+    loop {
+	match (rest.find("/*"), rest.find('"')) {
+	    (Some(comment), Some(quote)) if comment < quote => match rest[comment..].find("*/") {
+		Some(end) => rest = &rest[comment+end+2..],
+		None => break,
+	    },
+	    (_, Some(quote)) => {
+		let string = &rest[quote+1..];
+		match string.find('"') {
+		    Some(end) => {
+			ret.push(&string[..end]);
+			rest = &string[end+1..];
+		    },
+		    None => break,
+		}
+	    },
+	    _ => break,
+	}
+    }
+    ret
+}
+
+// Fits the image into a size*size square, centered and keeping its aspect ratio.
+// Each pixel is the average of the source pixels it covers.
+fn scale(image: &Image, size: usize) -> Vec<u32> {
+    let (w, h) = (image.width, image.height);
+    let ratio = size as f64 / w.max(h) as f64;
+    let dw = ((w as f64 * ratio).round() as usize).max(1).min(size);
+    let dh = ((h as f64 * ratio).round() as usize).max(1).min(size);
+    let (ox, oy) = ((size-dw)/2, (size-dh)/2);
+    let mut ret = vec![0u32; size*size];
+    for dy in 0..dh {
+	let y0 = dy*h/dh;
+	let y1 = ((dy+1)*h/dh).max(y0+1);
+	for dx in 0..dw {
+	    let x0 = dx*w/dw;
+	    let x1 = ((dx+1)*w/dw).max(x0+1);
+	    let mut sum = [0u32; 4];
+	    for sy in y0..y1 {
+		for sx in x0..x1 {
+		    let [r, g, b, a] = image.data[sy*w+sx];
+		    let a = a as u32;
+		    sum[0] += r as u32 * a / 255;
+		    sum[1] += g as u32 * a / 255;
+		    sum[2] += b as u32 * a / 255;
+		    sum[3] += a;
+		}
+	    }
+	    let n = ((x1-x0)*(y1-y0)) as u32;
+	    ret[(oy+dy)*size+ox+dx] = (sum[3]/n) << 24 | (sum[0]/n) << 16 | (sum[1]/n) << 8 | sum[2]/n;
+	}
+    }
+    ret
+}
@@ -1,91 +1,110 @@
-use x11::xlib::{XCreateGC, XCreatePixmap, XSetLineAttributes, XDefaultDepth, XDefaultColormap,
-		XDefaultVisual, JoinMiter, CapButt, LineSolid, XWindowAttributes,
-		Window, Display};
-use x11::xft::{XftColorAllocName, XftColor};
-use libc::{c_char, c_int, isatty};
-use std::{mem::MaybeUninit, ffi::CStr, ptr};
+use libc::isatty;
+use std::{collections::HashMap, io, mem::MaybeUninit};
 
+use crate::backend::{Backend, Color};
 use crate::drw::Drw;
 use crate::config::{Config, Schemes::*};
 use crate::item::Items;
+use crate::history::History;
+use crate::plugin::Plugin;
+use crate::modecmd::ModeCmd;
+use crate::mode::Modes;
+use crate::matching;
+use crate::replay;
+use crate::vi::Vi;
 use crate::util::*;
 use crate::globals::*;
-use crate::fnt::*;
 use crate::result::*;
 
 impl Drw {
-    pub fn new(dpy: *mut Display, screen: c_int, root: Window, wa: XWindowAttributes, pseudo_globals: PseudoGlobals, config: Config) -> CompResult<Self> {
+    pub fn new(backend: Box<dyn Backend>, pseudo_globals: PseudoGlobals, config: Config, plugins: Vec<Box<dyn Plugin>>) -> CompResult<Self> {
 	unsafe {
-	    let drawable = XCreatePixmap(dpy, root, wa.width as u32, wa.height as u32, XDefaultDepth(dpy, screen) as u32);
-	    let gc = XCreateGC(dpy, root, 0, ptr::null_mut());
-	    XSetLineAttributes(dpy, gc, 1, LineSolid, CapButt, JoinMiter);
-	    let mut ret = Self{wa, dpy, screen, root, drawable, gc, fonts: Vec::new(),
-			       pseudo_globals, config,
-			       scheme: MaybeUninit::uninit().assume_init(),
+	    let matcher = matching::matcher(&config.matching).unwrap(); // validated in clapflags
+	    let replay = replay::load(&config)?;
+	    let mut ret = Self{backend, pseudo_globals, config,
+			       scheme: Default::default(),
 			       w: MaybeUninit::uninit().assume_init(),
 			       h: MaybeUninit::uninit().assume_init(),
 			       input: "".to_string(),
-			       items: None};
+			       items: None,
+			       stdin_reader: None,
+			       history: None,
+			       color_cache: HashMap::new(),
+			       icons: HashMap::new(),
+			       widths: HashMap::new(),
+			       plugins,
+			       mode_cmd: None,
+			       modes: Modes::default(),
+			       matcher,
+			       replay,
+			       vi: Vi::default()};
 	    
-	    ret.fontset_create()?;
-	    ret.pseudo_globals.lrpad = ret.fonts[0].height as i32;
+	    if let Some(key) = ret.config.history_key.as_ref() {
+		ret.history = Some(History::load(key)?);
+	    }
+
+	    if let Some(command) = ret.config.mode_cmd.as_ref() {
+		ret.mode_cmd = Some(ModeCmd::spawn(command)?);
+	    }
+
+	    ret.backend.load_fonts(&ret.config.fontstrings)?;
+	    ret.pseudo_globals.lrpad = ret.backend.font_height() as i32;
 	    
 	    ret.items = if ret.config.nostdin {
 		ret.format_stdin(vec![])?;
-		grabkeyboard(ret.dpy, ret.config.embed)?;
+		ret.backend.grab_keyboard(&ret.config)?;
+		Some(Items::new(Vec::new()))
+	    } else if ret.config.incremental {
+		ret.backend.grab_keyboard(&ret.config)?;
+		ret.stdin_reader = Some(LineReader::spawn(io::stdin())?);
 		Some(Items::new(Vec::new()))
 	    } else {Some(Items::new(
 		if ret.config.fast && isatty(0) == 0 {
-		    grabkeyboard(ret.dpy, ret.config.embed)?;
+		    ret.backend.grab_keyboard(&ret.config)?;
 		    readstdin(&mut ret)?
 		} else {
 		    let tmp = readstdin(&mut ret)?;
-		    grabkeyboard(ret.dpy, ret.config.embed)?;
+		    ret.backend.grab_keyboard(&ret.config)?;
 		    tmp
 		}))
 	    };
+	    ret.init_modes()?;
 	    
 	    for j in 0..SchemeLast as usize {
 		ret.pseudo_globals.schemeset[j] = ret.scm_create(ret.config.colors[j])?;
 	    }
 
-	    ret.config.lines = ret.config.lines.min(ret.get_items().len() as u32);
+	    if ret.stdin_reader.is_none() && ret.mode_cmd.is_none()
+		&& ret.config.modes.is_empty() { // otherwise, item count isn't known yet
+		ret.config.lines = ret.config.lines.min(ret.get_items().len() as u32);
+	    }
 
 	    
 	    Ok(ret)
 	}
     }
 
-    fn scm_create(&self, clrnames: [[u8; 8]; 2]) -> CompResult<[*mut XftColor; 2]> {
-	let ret: [*mut XftColor; 2] = unsafe {
-	    [
-		Box::into_raw(Box::new(MaybeUninit::uninit().assume_init())),
-		Box::into_raw(Box::new(MaybeUninit::uninit().assume_init())),
-	    ]
-	};
-	self.clr_create(ret[0], clrnames[0].as_ptr() as *const c_char)?;
-	self.clr_create(ret[1], clrnames[1].as_ptr() as *const c_char)?;
-	Ok(ret)
+    fn scm_create(&mut self, clrnames: [[u8; 8]; 2]) -> CompResult<[Color; 2]> {
+	Ok([self.clr_create(&clrnames[0])?, self.clr_create(&clrnames[1])?])
     }
 
-    fn clr_create(&self, dest: *mut XftColor, clrname: *const c_char) -> CompResult<()> {
-	unsafe {
-	    if XftColorAllocName(self.dpy, XDefaultVisual(self.dpy, self.screen), XDefaultColormap(self.dpy, self.screen), clrname, dest) == 0 {
-		Die::stderr(format!("error, cannot allocate color {:?}", CStr::from_ptr(clrname)))
-	    } else {
-		Ok(())
-	    }
+    fn clr_create(&mut self, clrname: &[u8]) -> CompResult<Color> {
+	let len = clrname.iter().position(|c| *c == 0).unwrap_or(clrname.len());
+	let name = String::from_utf8_lossy(&clrname[..len]).to_string();
+	match self.backend.color(&name) {
+	    Some(color) => Ok(color),
+	    None => Die::stderr(format!("error, cannot allocate color {:?}", name)),
 	}
     }
 
-    fn fontset_create(&mut self) -> CompResult<()> {
-	for font in self.config.fontstrings.iter_mut() {
-	    font.push('\0');
+    /// Resolves a color by name, such as #ff0000 or red. None if the backend doesn't know it
+    /// Colors are cached for the lifetime of the Drw
+    pub fn color(&mut self, name: &str) -> Option<Color> {
+	if let Some(color) = self.color_cache.get(name) {
+	    return *color;
 	}
-	for font in self.config.fontstrings.iter() {
-	    self.fonts.push(Fnt::new(self, Some(font), ptr::null_mut())?);
-	}
-
-	Ok(())
+	let color = self.backend.color(name);
+	self.color_cache.insert(name.to_string(), color);
+	color
     }
 }
@@ -1,45 +1,115 @@
-use crate::drw::{Drw, TextOption::*};
-use crate::config::{Schemes::*, DefaultWidth};
+use crate::drw::{Drw, Run, TextOption::*};
+use crate::config::{Clrs::*, Schemes::*, DefaultWidth};
+use crate::markup::Style;
+use crate::matching::{Search, Tokens, char_ranges};
 use crate::result::*;
 
 use libc::c_int;
-use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+
+// How many of the longest items widest_item measures whenever items are added
+const WIDTH_SAMPLE: usize = 32;
 
 #[allow(unused_imports)]
-pub enum MatchCode {Exact, Prefix, Substring, None}
-pub use MatchCode::*;
+pub use crate::matching::MatchCode::{self, Exact, Prefix, Substring}; // not None, which would shadow Option's
 #[derive(Debug)]
 pub enum Direction {Vertical, Horizontal}
 pub use Direction::*;
 
+/// Something clickable drawn by Items::draw
+#[derive(Debug, Clone, Copy)]
+pub enum Hitbox {
+    Item(usize), // index into the current partition
+    PrevPage,
+    NextPage,
+}
+
+/// Cloned for every match each time the input changes, so all but the highlights are shared
 #[derive(Debug, Clone)]
 pub struct Item { // dmenu entry
-    pub text: String,
+    pub text: Arc<String>,
+    pub payload: Option<Arc<String>>, // printed instead of text, see --delimiter
     pub out: bool,
-    pub width: c_int,
+    pub marked: bool,
+    pub index: Option<usize>, // position in Items::data, None if generated on the fly
+    pub matched: Vec<(usize, usize)>, // byte ranges of text to highlight
+    pub styles: Arc<Vec<Style>>, // colors from --markup
+    pub icon: Option<Arc<String>>, // path to a png or xpm, see --delimiter
 }
 
 impl Item {
-    pub fn new(text: String, out: bool, drw: &mut Drw) -> CompResult<Self> {
-	Ok(Self{out, width: drw.textw(Other(&text))?, text})
+    pub fn new(text: String, out: bool) -> Self {
+	Self{out, marked: false, index: None, payload: None, matched: Vec::new(),
+	     styles: Arc::new(Vec::new()), icon: None, text: Arc::new(text)}
     }
-    pub fn draw(&self, x: c_int, y: c_int, w: c_int, drw: &mut Drw) -> CompResult<c_int> {
-	drw.text(x, y, w as u32, drw.pseudo_globals.bh as u32, drw.pseudo_globals.lrpad as u32/2, Other(&self.text), false).map(|o| o.0)
+    pub fn set_icon(&mut self, path: String) {
+	self.icon = Some(Arc::new(path));
     }
-    #[allow(unused)] // won't be used if overriden
-    pub fn matches(&self, re: &Regex) -> MatchCode {
-	match re.find_iter(&self.text)
-	    .nth(0).map(|m| (m.start(), m.end()))
-	    .unwrap_or((1,0)) {
-		(1, 0) => MatchCode::None, // don't expect zero length matches...
-		(0, end) => //                unless search is empty
-		    if end == self.text.len() {
-			MatchCode::Exact
-		    } else {
-			MatchCode::Prefix
-		    },
-		_ => MatchCode::Substring,
+    /// Width when drawn, measured the first time it's needed, see Drw::text_width
+    pub fn width(&self, drw: &mut Drw) -> CompResult<c_int> {
+	let text = drw.text_width(&self.text)?;
+	Ok(if self.icon.is_some() {text + drw.icon_width()} else {text})
+    }
+    pub fn output(&self) -> &String { // what gets disposed of when selected
+	self.payload.as_deref().unwrap_or(&self.text)
+    }
+    pub fn draw(&self, mut x: c_int, y: c_int, mut w: c_int, drw: &mut Drw) -> CompResult<c_int> {
+	let scheme = drw.scheme;
+	let selected = scheme == drw.pseudo_globals.schemeset[SchemeSel as usize];
+	let highlight = if selected {
+	    drw.pseudo_globals.schemeset[SchemeSelHl as usize]
+	} else if scheme == drw.pseudo_globals.schemeset[SchemeNorm as usize] {
+	    drw.pseudo_globals.schemeset[SchemeNormHl as usize]
+	} else { // marked or out, keep its background
+	    [drw.pseudo_globals.schemeset[SchemeNormHl as usize][ColFg as usize], scheme[ColBg as usize]]
+	};
+	// match highlights come first, so they win over markup
+	let mut runs: Vec<Run> = self.matched.iter().map(|(start, end)| (*start, *end, highlight)).collect();
+	for style in self.styles.iter() {
+	    let fg = style.fg.as_ref().and_then(|c| drw.color(c)).unwrap_or(scheme[ColFg as usize]);
+	    let bg = match style.bg.as_ref() {
+		Some(c) if !selected => drw.color(c).unwrap_or(scheme[ColBg as usize]),
+		_ => scheme[ColBg as usize], // keep the selection visible
+	    };
+	    if style.start == 0 && style.end == self.text.len() && !selected {
+		drw.scheme = [fg, bg]; // color the padding too
+	    } else {
+		runs.push((style.start, style.end, [fg, bg]));
 	    }
+	}
+	if let Some(icon) = self.icon.as_ref() {
+	    let slot = drw.icon_width().min(w);
+	    let bh = drw.pseudo_globals.bh as c_int;
+	    drw.rect(x, y, slot as u32, bh as u32, true, true);
+	    drw.draw_icon(icon, x + drw.pseudo_globals.lrpad/2, y + (bh - drw.icon_size() as c_int)/2);
+	    x += slot;
+	    w -= slot;
+	}
+	let ret = drw.text(x, y, w as u32, drw.pseudo_globals.bh as u32, drw.pseudo_globals.lrpad as u32/2, Styled(&self.text, runs), false).map(|o| o.0);
+	drw.scheme = scheme;
+	ret
+    }
+    /// Highlights the chars at the given char (not byte) indices
+    /// Consecutive chars are merged into one range
+    #[allow(unused)] // for plugins
+    pub fn set_matched_chars(&mut self, indices: &[usize]) {
+	self.matched = char_ranges(&self.text, indices);
+    }
+    /// Matches like dmenu, highlighting every token found. See matching::Tokens
+    #[allow(unused)] // for plugins
+    pub fn matches(&mut self, tokens: &Tokens) -> MatchCode {
+	match tokens.find(&self.text) {
+	    Some((code, matched)) => {
+		self.matched = matched;
+		code
+	    },
+	    None => {
+		self.matched.clear();
+		MatchCode::None
+	    },
+	}
     }
 }
 
@@ -85,17 +155,49 @@ pub struct Items {
     pub data: Vec<Item>,
     pub cached_partitions: Vec<Partition>, // seperated into screens
     pub curr: usize,
+    pub marked: Vec<usize>, // indices into data, in the order they were marked
+    pub search: Search, // the last search through data, see builtin_matches
+    pub widest: (usize, c_int), // items sampled by Drw::widest_item, and the widest of them
+    pub hitboxes: Vec<(c_int, c_int, Hitbox)>, // spans along the direction items were last drawn in
 }
 
 impl Items {
-    pub fn new(data: Vec<Item>) -> Self {
-	Self{data, cached_partitions: Vec::new(), curr: 0}
+    pub fn new(mut data: Vec<Item>) -> Self {
+	for (i, item) in data.iter_mut().enumerate() {
+	    item.index = Some(i);
+	}
+	Self{data, cached_partitions: Vec::new(), curr: 0, marked: Vec::new(),
+	     search: Search::default(), widest: (0, 0), hitboxes: Vec::new()}
     }
     pub fn match_len(&self) -> usize {
 	self.cached_partitions.len()
     }
+    pub fn extend(&mut self, new: Vec<Item>) {
+	let offset = self.data.len();
+	self.data.extend(new.into_iter().enumerate().map(|(i, mut item)| {
+	    item.index = Some(offset+i);
+	    item
+	}));
+    }
+    pub fn toggle_mark(&mut self, index: usize) {
+	let item = &mut self.data[index];
+	item.marked = !item.marked;
+	if item.marked {
+	    self.marked.push(index);
+	} else {
+	    self.marked.retain(|i| *i != index);
+	}
+    }
+    pub fn clear_marks(&mut self) {
+	for index in self.marked.drain(..) {
+	    self.data[index].marked = false;
+	}
+    }
     pub fn draw(drw: &mut Drw, direction: Direction) -> CompResult<bool> { // gets an apropriate vec of matches
-	let pre_processed_items = drw.gen_matches()?;
+	let mut pre_processed_items = drw.mode_gen_matches()?;
+	if let Some(history) = drw.history.as_ref() {
+	    pre_processed_items = history.rank(pre_processed_items);
+	}
 	let items_to_draw = drw.postprocess_matches(pre_processed_items)?;
 	let rangle = ">".to_string();
 	let rangle_width = drw.textw(Other(&rangle))?;
@@ -104,20 +206,37 @@ impl Items {
 
 	drw.pseudo_globals.inputw =
 	    match drw.config.render_default_width {
-		DefaultWidth::Min => items_to_draw.iter()
-		    .fold(0, |acc, w| acc.max(w.width))
-		    .min(drw.w/3)
-		    .min(drw.textw(Input)?),
-		DefaultWidth::Items => drw.get_items().iter()
-		    .fold(0, |acc, w| acc.max(w.width))
+		DefaultWidth::Min => {
+		    let cap = (drw.w/3).min(drw.textw(Input)?);
+		    let curr = drw.items.as_ref().unwrap().curr;
+		    let mut widest = 0;
+		    let mut past = 0; // width of the items after the selection
+		    // only up to a screen past the selection, as partition_matches lays out no further
+		    for (i, item) in items_to_draw.iter().enumerate() {
+			if widest >= cap || past >= drw.w {
+			    break;
+			}
+			let width = item.width(drw)?;
+			widest = widest.max(width);
+			if i > curr {
+			    past += width;
+			}
+		    }
+		    widest.min(cap)
+		},
+		DefaultWidth::Items => drw.widest_item()?
 		    .min(drw.w/3),
 		DefaultWidth::Max => {
 		    let curr = drw.items.as_ref().unwrap().curr;
-		    let data = drw.get_items();
+		    let len = drw.get_items().len();
+		    let width = match drw.get_items().get(curr).cloned() {
+			Some(item) => item.width(drw)?,
+			None => 0,
+		    };
 		    let mut w = drw.w
 			- drw.pseudo_globals.promptw
-			- data[curr].width;
-		    if curr < data.len()-1 {
+			- width;
+		    if curr + 1 < len { // len is 0 before stdin has anything
 			w -= rangle_width;
 		    }
 		    if curr > 0 {
@@ -139,8 +258,10 @@ impl Items {
 
 	if matched_partitions.len() == 0 {
 	    drw.items.as_mut().unwrap().cached_partitions = matched_partitions;
+	    drw.items.as_mut().unwrap().hitboxes.clear();
 	    return Ok(false); // nothing to draw
 	}
+	let mut hitboxes = Vec::new();
 	
 	let (partition_i, partition) = Partition::decompose(&matched_partitions, drw);
 	
@@ -169,7 +290,9 @@ impl Items {
 	    if partition > 0 {
 		// draw langle if required
 		drw.setscheme(SchemeNorm);
+		let start = coord;
 		coord = drw.text(coord, 0, langle_width as u32, drw.pseudo_globals.bh as u32, drw.pseudo_globals.lrpad as u32/2, Other(&langle), false)?.0;
+		hitboxes.push((start, coord, Hitbox::PrevPage));
 		if drw.config.render_default_width == DefaultWidth::Max {
 		    // This is here due do an optical illusion
 		    // It's not pedantically correct alignment, but makes sense on Max
@@ -186,39 +309,55 @@ impl Items {
 	for index in 0..matched_partitions[partition].len() {
 	    if index == partition_i {
 		drw.setscheme(SchemeSel);
+	    } else if matched_partitions[partition][index].marked {
+		drw.setscheme(SchemeMark);
 	    } else if matched_partitions[partition][index].out {
 		drw.setscheme(SchemeOut);
 	    } else {   
 		drw.setscheme(SchemeNorm);
 	    }
+	    let start = coord;
 	    match direction {
 		Horizontal => {
 		    if partition+1 < matched_partitions.len() { // draw rangle
+			let width = matched_partitions[partition][index].width(drw)?;
 			coord = matched_partitions[partition][index]
-			    .draw(coord, 0, matched_partitions[partition][index]
-				  .width.min(drw.w - coord - rangle_width), drw)?;
+			    .draw(coord, 0, width.min(drw.w - coord - rangle_width), drw)?;
 			drw.setscheme(SchemeNorm);
 			drw.text(drw.w - rangle_width, 0, rangle_width as u32, drw.pseudo_globals.bh as u32, drw.pseudo_globals.lrpad as u32/2, Other(&rangle), false)?;
 		    } else { // no rangle
+			let width = matched_partitions[partition][index].width(drw)?;
 			coord = matched_partitions[partition][index]
-			    .draw(coord, 0, matched_partitions[partition][index]
-				  .width.min(drw.w - coord), drw)?;
+			    .draw(coord, 0, width.min(drw.w - coord), drw)?;
 		    }
+		    hitboxes.push((start, coord, Hitbox::Item(index)));
 		},
 		Vertical => {
 		    coord += matched_partitions[partition][index].draw(0, coord, drw.w, drw)?;
+		    hitboxes.push((start, start + drw.pseudo_globals.bh as c_int, Hitbox::Item(index)));
 		}
 	    }	    
 	}
+	if let Horizontal = direction {
+	    if partition+1 < matched_partitions.len() {
+		hitboxes.push((drw.w - rangle_width, drw.w, Hitbox::NextPage));
+	    }
+	}
 
 	drw.items.as_mut().unwrap().cached_partitions = matched_partitions;
+	drw.items.as_mut().unwrap().hitboxes = hitboxes;
 	
 	Ok(true)
     }
     
+    /// Horizontally, only pages up to the one with the selection are laid out, the rest of the
+    /// matches are left in one last partition. That way only those items need to be measured,
+    /// and moving past it lays out the next page.
     fn partition_matches(input: Vec<Item>, direction: &Direction, drw: &mut Drw, langle_width: i32, rangle_width: i32) -> CompResult<Vec<Partition>> { // matches come in, partitions come out
 	match direction {
 	    Horizontal => {
+		let curr = drw.items.as_ref().unwrap().curr;
+		let mut laid_out = 0; // items in finished partitions
 		let mut partitions = Vec::new();
 		let mut partition_build = Vec::new();
 		let mut x = if drw.config.render_default_width == DefaultWidth::Items {
@@ -229,9 +368,10 @@ impl Items {
 		};
 		let mut item_iter = input.into_iter().peekable();
 		while let Some(item) = item_iter.next() {
+		    let item_width = item.width(drw)?;
 		    let precomp_width = x;
 		    let leftover;
-		    x += item.width;
+		    x += item_width;
 		    if x > {
 			let width_comp = if item_iter.peek().is_some() {
 			    drw.w - rangle_width
@@ -245,10 +385,16 @@ impl Items {
 			     && item_iter.peek().is_none()   // there will only be one page
 			     && x < drw.w + rangle_width     // and everything could fit if it wasn't for the '>'
 			     ) && partition_build.len() > 0 { // (make sure no empties)
+			    laid_out += partition_build.len();
 			    partitions.push(Partition::new(partition_build, leftover));
+			    if laid_out > curr { // the selection's page is done
+				let rest: Vec<Item> = std::iter::once(item).chain(item_iter).collect();
+				partitions.push(Partition::new(rest, 0));
+				return Ok(partitions);
+			    }
 			    partition_build = Vec::new();
 			    x = drw.pseudo_globals.promptw + drw.pseudo_globals.inputw
-				+ langle_width + item.width;
+				+ langle_width + item_width;
 			}
 		    }
 		    partition_build.push(item);
@@ -277,9 +423,50 @@ impl Drw {
     pub fn get_items(&self) -> &Vec<Item> {
 	&self.items.as_ref().unwrap().data
     }
+    /// Items may be changed, so the next search and widest_item start over
     #[allow(unused)] // for plugins
-    #[inline(always)]
     pub fn get_items_mut(&mut self) -> &mut Vec<Item> {
-	&mut self.items.as_mut().unwrap().data
+	let items = self.items.as_mut().unwrap();
+	items.search = Search::default();
+	items.widest = (0, 0);
+	&mut items.data
+    }
+    /// About the width of the widest item, for --render_default_width items.
+    /// Measuring every item would hold up startup, so only the longest few in bytes are,
+    /// as items are added.
+    pub fn widest_item(&mut self) -> CompResult<c_int> {
+	let (sampled, mut widest) = self.items.as_ref().unwrap().widest;
+	let data = self.get_items();
+	if sampled == data.len() {
+	    return Ok(widest);
+	}
+	let mut longest = BinaryHeap::new();
+	for (i, item) in data.iter().enumerate().skip(sampled) {
+	    longest.push(Reverse((item.text.len(), i)));
+	    if longest.len() > WIDTH_SAMPLE {
+		longest.pop(); // the shortest
+	    }
+	}
+	let sample: Vec<Item> = longest.into_iter().map(|Reverse((_, i))| data[i].clone()).collect();
+	for item in sample {
+	    widest = widest.max(item.width(self)?);
+	}
+	let len = self.get_items().len();
+	self.items.as_mut().unwrap().widest = (len, widest);
+	Ok(widest)
+    }
+    /// Items matching the input, ranked by the matcher from --matching
+    /// Used by gen_matches unless overridden, and by modes
+    pub fn builtin_matches(&mut self) -> CompResult<Vec<Item>> {
+	let case_sensitive = self.config.case_sensitive;
+	let Items{data, search, ..} = self.items.as_mut().unwrap();
+	let data = &*data;
+	let found = search.run(&mut *self.matcher, &self.input, case_sensitive, data.len(),
+			       |i| data[i].text.as_str());
+	Ok(found.into_iter().map(|(i, matched)| {
+	    let mut item = data[i].clone();
+	    item.matched = matched;
+	    item
+	}).collect())
     }
 }
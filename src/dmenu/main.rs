@@ -9,16 +9,36 @@ mod init;
 mod setup;
 mod run;
 mod clapflags;
+mod conffile;
 mod plugin_entry;
 mod result;
+mod history;
+mod icon;
+mod markup;
+mod plugin;
+mod dylib;
+mod modecmd;
+mod mode;
+mod ssh;
+mod windows;
+mod drun;
+mod executables;
+mod matching;
+mod backend;
+mod bindings;
+mod xorg;
+#[cfg(any(feature = "headless", feature = "wayland"))]
+mod canvas;
+#[cfg(feature = "headless")]
+mod headless;
+mod replay;
+mod vi;
+#[cfg(feature = "wayland")]
+mod wayland;
 mod plugins {
     include!(concat!(env!("OUT_DIR"), "/proc_mod_plugin.rs"));
 }
 
-use x11::xlib::*;
-use std::ptr;
-use libc::{setlocale, LC_CTYPE};
-use std::mem::MaybeUninit;
 #[cfg(target_os = "openbsd")]
 use pledge;
 
@@ -46,32 +66,29 @@ fn main() { // just a wrapper to ensure a clean death in the event of error
 }
 
 fn try_main() -> CompResult<()> {
+    let mut plugins = plugin::load()?;
     let mut config = Config::default();
     let pseudo_globals = PseudoGlobals::default();
 
+    plugin::apply_defaults(&mut config)?;
+    conffile::load(&mut config)?;
     clapflags::validate(&mut config)?;
+    plugin::init(&mut plugins)?;
     
-    unsafe {	
-	if setlocale(LC_CTYPE, ptr::null())==ptr::null_mut() || XSupportsLocale()==0 {
-	    return Die::stderr("warning: no locale support".to_owned());
+    let backend = backend::open(&config)?;
+    let mut drw = Drw::new(backend, pseudo_globals, config, plugins)?;
+    if cfg!(target_os = "openbsd") {
+	let mut promises = vec!["stdio", "rpath"];
+	if drw.config.render_to.is_some() || drw.config.history_key.is_some() {
+	    promises.extend(&["wpath", "cpath"]); // the image or history is written later
 	}
-	let dpy = XOpenDisplay(ptr::null_mut());
-	if dpy==ptr::null_mut() {
-	    return Die::stderr("cannot open display".to_owned());
+	if drw.config.modes.iter().any(|mode| ["run", "drun", "ssh"].contains(&mode.as_str())) {
+	    promises.extend(&["proc", "exec"]); // these modes run the selection
 	}
-	let screen = XDefaultScreen(dpy);
-	let root = XRootWindow(dpy, screen);
-	let parentwin = root.max(config.embed);
-	let mut wa: XWindowAttributes = MaybeUninit::uninit().assume_init();
-	XGetWindowAttributes(dpy, parentwin, &mut wa);
-
-	let mut drw = Drw::new(dpy, screen, root, wa, pseudo_globals, config)?;
-	if cfg!(target_os = "openbsd") {
-	    pledge::pledge("stdio rpath", None)
-		.map_err(|_| Die::Stderr("Could not pledge".to_owned()))?;
-	}
-	
-	drw.setup(parentwin, root)?;
-	drw.run()
+	pledge::pledge(promises.join(" ").as_str(), None)
+	    .map_err(|_| Die::Stderr("Could not pledge".to_owned()))?;
     }
+
+    drw.setup()?;
+    drw.run()
 }
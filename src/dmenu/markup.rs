@@ -0,0 +1,82 @@
+/// A byte range of an item's text with its own colors
+/// Colors are anything the backend understands, such as #ff0000, or red on X11
+#[derive(Debug, Clone)]
+pub struct Style {
+    pub start: usize,
+    pub end: usize,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+}
+
+/// Strips markup from a line, returning the plain text and its styles
+/// Supported is a small subset of Pango markup:
+/// - `<span fg="#f00" bg="#000">...</span>`, foreground/background/color also work
+/// - the entities &lt; &gt; &amp; &quot; and &apos;
+/// Anything else is kept as-is. Inner spans come before outer ones in the output.
+pub fn parse(line: &str) -> (String, Vec<Style>) {
+    let mut text = String::new();
+    let mut styles = Vec::new();
+    let mut open: Vec<Style> = Vec::new();
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+	if c == '<' {
+	    if let Some(close) = rest.find('>') {
+		let tag = &rest[1..close];
+		if tag == "/span" {
+		    if let Some(mut style) = open.pop() {
+			style.end = text.len();
+			styles.push(style);
+			rest = &rest[close+1..];
+			continue;
+		    }
+		} else if tag == "span" || tag.starts_with("span ") {
+		    if let Some((fg, bg)) = parse_attrs(&tag[4..]) {
+			let outer = open.last();
+			open.push(Style{
+			    start: text.len(),
+			    end: text.len(),
+			    fg: fg.or_else(|| outer.and_then(|s| s.fg.clone())),
+			    bg: bg.or_else(|| outer.and_then(|s| s.bg.clone())),
+			});
+			rest = &rest[close+1..];
+			continue;
+		    }
+		}
+	    }
+	} else if c == '&' {
+	    let entity = [("&lt;", '<'), ("&gt;", '>'), ("&amp;", '&'),
+			  ("&quot;", '"'), ("&apos;", '\'')]
+		.iter().find(|(name, _)| rest.starts_with(name));
+	    if let Some((name, replacement)) = entity {
+		text.push(*replacement);
+		rest = &rest[name.len()..];
+		continue;
+	    }
+	}
+	text.push(c);
+	rest = &rest[c.len_utf8()..];
+    }
+    for mut style in open.into_iter().rev() { // unclosed spans run to the end
+	style.end = text.len();
+	styles.push(style);
+    }
+    styles.retain(|style| style.start < style.end);
+    (text, styles)
+}
+
+// Parses ` fg="#f00" bg='#000'`. None if anything isn't understood
+fn parse_attrs(attrs: &str) -> Option<(Option<String>, Option<String>)> {
+    let mut fg = None;
+    let mut bg = None;
+    for attr in attrs.split_whitespace() {
+	let mut kv = attr.splitn(2, '=');
+	let key = kv.next()?;
+	let value = kv.next()?.trim_matches(|c| c == '"' || c == '\'').to_string();
+	match key {
+	    "fg" | "foreground" | "color" => fg = Some(value),
+	    "bg" | "background" => bg = Some(value),
+	    _ => return None,
+	}
+    }
+    Some((fg, bg))
+}
@@ -0,0 +1,367 @@
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use std::fmt::{self, Debug};
+
+// Nothing from the rest of dmenu is used here, so benches/matching.rs can include this file
+
+/// Where a match was found, ranked in this order
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum MatchCode {Exact, Prefix, Substring, None}
+
+/// Matchers in the order Alt-m cycles through them
+pub const MATCHERS: [&str; 7] = ["exact", "prefix", "substring", "fuzzy", "regex", "glob", "tokens"];
+
+/// Ranks matches, lower first. Ties keep the order items came in.
+pub type Rank = (i64, usize);
+
+/// A way of matching items against the input, see --matching
+/// Items are ranked from several threads at once.
+pub trait Matcher: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Called with the input before any items are ranked
+    fn prepare(&mut self, input: &str, case_sensitive: bool);
+    /// None if text doesn't match, otherwise its rank and the byte ranges to highlight
+    fn rank(&self, text: &str) -> Option<(Rank, Vec<(usize, usize)>)>;
+    /// Whether anything matching some input also matches that input with something appended,
+    /// so that typing only has to look at what matched before
+    fn narrows(&self) -> bool {
+	false
+    }
+}
+
+/// The matcher called name, if there is one
+pub fn matcher(name: &str) -> Option<Box<dyn Matcher>> {
+    Some(match name {
+	"exact" => Box::new(Literal{name: "exact", accept: MatchCode::Exact, re: None}),
+	"prefix" => Box::new(Literal{name: "prefix", accept: MatchCode::Prefix, re: None}),
+	"substring" => Box::new(Literal{name: "substring", accept: MatchCode::Substring, re: None}),
+	"fuzzy" => Box::new(Fuzzy{input: String::new(), case_sensitive: None,
+				  skim: SkimMatcherV2::default()}),
+	"regex" => Box::new(Pattern{name: "regex", re: None}),
+	"glob" => Box::new(Pattern{name: "glob", re: None}),
+	"tokens" => Box::new(Tokens::new("", true)),
+	_ => return None,
+    })
+}
+
+/// The matcher after current in MATCHERS, wrapping around
+pub fn next(current: &str) -> Box<dyn Matcher> {
+    let i = MATCHERS.iter().position(|name| *name == current).unwrap_or(0);
+    matcher(MATCHERS[(i+1) % MATCHERS.len()]).unwrap()
+}
+
+/// The last search, so that narrowing it only looks at what matched
+#[derive(Debug, Default)]
+pub struct Search {
+    input: String,
+    matcher: &'static str,
+    case_sensitive: bool,
+    searched: usize, // how many items there were
+    found: Vec<usize>, // indices of the matches, ascending
+}
+
+impl Search {
+    /// Indices of the items matching input, best first, with the byte ranges to highlight.
+    /// There are len items, and text(i) is the text of the i-th. Items may be appended
+    /// between searches, but if any others change, the search has to start over.
+    pub fn run<'a, F>(&mut self, matcher: &mut dyn Matcher, input: &str, case_sensitive: bool,
+		      len: usize, text: F) -> Vec<(usize, Vec<(usize, usize)>)>
+    where F: Fn(usize) -> &'a str + Sync {
+	let candidates: Vec<usize> = if matcher.narrows() && self.matcher == matcher.name()
+	    && self.case_sensitive == case_sensitive && input.starts_with(&self.input)
+	    && self.searched <= len {
+		self.found.iter().cloned().chain(self.searched..len).collect()
+	    } else {
+		(0..len).collect()
+	    };
+	self.input = input.to_string();
+	self.matcher = matcher.name();
+	self.case_sensitive = case_sensitive;
+	self.searched = len;
+	if input.len() == 0 {
+	    self.found = candidates;
+	    return self.found.iter().map(|i| (*i, Vec::new())).collect();
+	}
+	matcher.prepare(input, case_sensitive);
+	let matcher = &*matcher;
+	let mut ranked: Vec<(Rank, usize, Vec<(usize, usize)>)> = candidates.par_iter()
+	    .filter_map(|i| matcher.rank(text(*i)).map(|(rank, matched)| (rank, *i, matched)))
+	    .collect(); // in the order of candidates
+	self.found = ranked.iter().map(|(_, i, _)| *i).collect();
+	ranked.par_sort_by_key(|(rank, _, _)| *rank); // stable, so ties keep their order
+	ranked.into_iter().map(|(_, i, matched)| (i, matched)).collect()
+    }
+}
+
+/// Converts char (not byte) indices into byte ranges, merging consecutive chars
+pub fn char_ranges(text: &str, indices: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    let last = match indices.iter().max() {
+	Some(last) => *last,
+	None => return ranges,
+    };
+    for (i, (byte, c)) in text.char_indices().enumerate().take(last+1) {
+	if indices.contains(&i) {
+	    match ranges.last_mut() {
+		Some((_, end)) if *end == byte => *end += c.len_utf8(),
+		_ => ranges.push((byte, byte+c.len_utf8())),
+	    }
+	}
+    }
+    ranges
+}
+
+fn build(pattern: &str, case_sensitive: bool) -> Option<Regex> {
+    RegexBuilder::new(pattern).case_insensitive(!case_sensitive).build().ok()
+}
+
+// The first non-empty match of re in text, and where it was found
+fn find(re: &Regex, text: &str) -> Option<(MatchCode, Vec<(usize, usize)>)> {
+    let m = re.find_iter(text).find(|m| m.start() < m.end())?;
+    let code = match (m.start(), m.end()) {
+	(0, end) if end == text.len() => MatchCode::Exact,
+	(0, _) => MatchCode::Prefix,
+	_ => MatchCode::Substring,
+    };
+    Some((code, vec![(m.start(), m.end())]))
+}
+
+/// The input as-is. Exact matches come first, then prefix matches, then substring matches,
+/// each only if accepted.
+#[derive(Debug)]
+struct Literal {
+    name: &'static str,
+    accept: MatchCode, // this and any better
+    re: Option<Regex>,
+}
+
+impl Matcher for Literal {
+    fn name(&self) -> &'static str {
+	self.name
+    }
+    fn prepare(&mut self, input: &str, case_sensitive: bool) {
+	self.re = build(&regex::escape(input), case_sensitive);
+    }
+    fn rank(&self, text: &str) -> Option<(Rank, Vec<(usize, usize)>)> {
+	let (code, matched) = find(self.re.as_ref()?, text)?;
+	if code <= self.accept {
+	    Some(((code as i64, 0), matched))
+	} else {
+	    None
+	}
+    }
+    fn narrows(&self) -> bool {
+	self.accept != MatchCode::Exact
+    }
+}
+
+/// Skim's fuzzy matching, best scores first and shorter items breaking ties.
+/// Case is ignored with --insensitive, and otherwise only if the input is all lowercase
+struct Fuzzy {
+    input: String,
+    case_sensitive: Option<bool>, // what skim was made for
+    skim: SkimMatcherV2,
+}
+
+impl Debug for Fuzzy { // SkimMatcherV2 isn't
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	f.debug_struct("Fuzzy").field("input", &self.input).finish()
+    }
+}
+
+impl Matcher for Fuzzy {
+    fn name(&self) -> &'static str {
+	"fuzzy"
+    }
+    fn prepare(&mut self, input: &str, case_sensitive: bool) {
+	self.input = input.to_string();
+	if self.case_sensitive != Some(case_sensitive) {
+	    self.case_sensitive = Some(case_sensitive);
+	    self.skim = if case_sensitive {
+		SkimMatcherV2::default().smart_case()
+	    } else {
+		SkimMatcherV2::default().ignore_case()
+	    };
+	}
+    }
+    fn rank(&self, text: &str) -> Option<(Rank, Vec<(usize, usize)>)> {
+	let (score, indices) = self.skim.fuzzy_indices(text, &self.input)?;
+	Some(((-score, text.len()), char_ranges(text, &indices)))
+    }
+    fn narrows(&self) -> bool {
+	true // the input is a subsequence, and case only matters once there are capitals
+    }
+}
+
+/// The input as a regular expression, or a glob where * and ? are wildcards and [...]
+/// is a set of chars. Either may match anywhere in the item, and is ranked like substring.
+/// Until the input is a valid pattern, nothing matches.
+#[derive(Debug)]
+struct Pattern {
+    name: &'static str,
+    re: Option<Regex>,
+}
+
+impl Matcher for Pattern {
+    fn name(&self) -> &'static str {
+	self.name
+    }
+    fn prepare(&mut self, input: &str, case_sensitive: bool) {
+	self.re = if self.name == "glob" {
+	    build(&glob_to_regex(input), case_sensitive)
+	} else {
+	    build(input, case_sensitive)
+	};
+    }
+    fn rank(&self, text: &str) -> Option<(Rank, Vec<(usize, usize)>)> {
+	let (code, matched) = find(self.re.as_ref()?, text)?;
+	Some(((code as i64, 0), matched))
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut ret = String::new();
+    let mut set: Option<usize> = None; // chars so far, while in [...]
+    for c in glob.chars() {
+	match (c, set) {
+	    ('*', None) => ret.push_str(".*"),
+	    ('?', None) => ret.push('.'),
+	    ('[', None) => ret.push('['),
+	    (']', Some(len)) if len > 0 => ret.push(']'),
+	    ('!', Some(0)) => ret.push('^'),
+	    ('\\', Some(_)) | ('^', Some(_)) | ('[', Some(_)) | (']', Some(_)) => {
+		ret.push('\\');
+		ret.push(c);
+	    },
+	    (_, Some(_)) => ret.push(c),
+	    (_, None) => ret.push_str(&regex::escape(&c.to_string())),
+	}
+	set = match (c, set) {
+	    ('[', None) => Some(0),
+	    (']', Some(len)) if len > 0 => None,
+	    (_, Some(len)) => Some(len+1),
+	    (_, None) => None,
+	};
+    }
+    ret
+}
+
+/// dmenu's matching, and the default: the input is split on spaces, and every token has
+/// to be found. Items equal to the whole input come first, then those starting with the
+/// first token. Used by Item::matches as well.
+#[derive(Debug)]
+pub struct Tokens {
+    whole: Option<Regex>,
+    tokens: Vec<Regex>,
+}
+
+impl Tokens {
+    pub fn new(input: &str, case_sensitive: bool) -> Self {
+	Self{
+	    whole: build(&format!("^{}$", regex::escape(input)), case_sensitive),
+	    tokens: input.split(' ').filter(|token| token.len() > 0)
+		.filter_map(|token| build(&regex::escape(token), case_sensitive))
+		.collect(),
+	}
+    }
+
+    /// None if text doesn't contain every token, otherwise how it matched and the byte
+    /// ranges to highlight. Without tokens, everything matches exactly with nothing highlighted.
+    pub fn find(&self, text: &str) -> Option<(MatchCode, Vec<(usize, usize)>)> {
+	let mut found = Vec::new();
+	let mut first = None;
+	for token in &self.tokens {
+	    let (code, ranges) = find(token, text)?;
+	    first.get_or_insert(code);
+	    found.extend(ranges);
+	}
+	found.sort();
+	let mut matched: Vec<(usize, usize)> = Vec::new(); // tokens may overlap
+	for (start, end) in found {
+	    match matched.last_mut() {
+		Some((_, last)) if *last >= start => *last = (*last).max(end),
+		_ => matched.push((start, end)),
+	    }
+	}
+	let code = if self.tokens.is_empty() // like upstream, all spaces is an exact match for anything
+	    || self.whole.as_ref().map(|re| re.is_match(text)).unwrap_or(false) {
+	    MatchCode::Exact
+	} else if first != Some(MatchCode::Substring) {
+	    MatchCode::Prefix
+	} else {
+	    MatchCode::Substring
+	};
+	Some((code, matched))
+    }
+}
+
+impl Matcher for Tokens {
+    fn name(&self) -> &'static str {
+	"tokens"
+    }
+    fn prepare(&mut self, input: &str, case_sensitive: bool) {
+	*self = Tokens::new(input, case_sensitive);
+    }
+    fn rank(&self, text: &str) -> Option<(Rank, Vec<(usize, usize)>)> {
+	let (code, matched) = self.find(text)?;
+	Some(((code as i64, 0), matched))
+    }
+    fn narrows(&self) -> bool {
+	true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Indices of items matching input with matcher, best first
+    fn search(matcher: &str, input: &str, case_sensitive: bool, items: &[&str]) -> Vec<usize> {
+	let mut matcher = super::matcher(matcher).unwrap();
+	Search::default().run(&mut *matcher, input, case_sensitive, items.len(), |i| items[i])
+	    .into_iter().map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn tokens_match_anywhere() {
+	let tokens = Tokens::new("fire priv", true);
+	assert_eq!(tokens.find("firefox --private-window"),
+		   Some((MatchCode::Prefix, vec![(0, 4), (10, 14)])));
+	assert_eq!(tokens.find("firefox"), None);
+	assert_eq!(Tokens::new("priv fire", true).find("firefox --private-window"),
+		   Some((MatchCode::Substring, vec![(0, 4), (10, 14)])));
+    }
+
+    #[test]
+    fn tokens_rank_exact_then_prefix_then_substring() {
+	let items = ["a foo", "foobar", "foo", "bar"];
+	assert_eq!(search("tokens", "foo", true, &items), vec![2, 1, 0]);
+	assert_eq!(search("tokens", "foo bar", true, &["bar foo", "foobar", "foo bar"]), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn tokens_case_insensitive() {
+	assert_eq!(Tokens::new("FOO", false).find("a foo"),
+		   Some((MatchCode::Substring, vec![(2, 5)])));
+	assert_eq!(Tokens::new("FOO", true).find("a foo"), None);
+	assert_eq!(search("tokens", "Foo", false, &["foo", "FOOBAR", "bar"]), vec![0, 1]);
+    }
+
+    #[test]
+    fn tokens_empty_input() {
+	for input in &["", "   "] {
+	    assert_eq!(Tokens::new(input, true).find("anything"), Some((MatchCode::Exact, Vec::new())));
+	}
+	assert_eq!(search("tokens", "", true, &["b", "a"]), vec![0, 1]);
+	assert_eq!(search("tokens", "  ", true, &["b", "a"]), vec![0, 1]);
+    }
+
+    #[test]
+    fn tokens_merge_highlights() {
+	assert_eq!(Tokens::new("abc bcd", true).find("xabcde"), Some((MatchCode::Substring, vec![(1, 5)])));
+	assert_eq!(Tokens::new("ab cd", true).find("abcd"), Some((MatchCode::Prefix, vec![(0, 4)])));
+	assert_eq!(Tokens::new("b ab", true).find("ab b"), Some((MatchCode::Substring, vec![(0, 2)])));
+    }
+}
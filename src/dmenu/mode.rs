@@ -0,0 +1,183 @@
+use std::fmt::Debug;
+use libc::c_int;
+
+use crate::drw::Drw;
+use crate::item::{Item, Items};
+use crate::drun::Drun;
+use crate::executables::Executables;
+use crate::modecmd::CmdMode;
+use crate::plugin::PluginMode;
+use crate::ssh::Ssh;
+use crate::windows::Windows;
+use crate::result::*;
+
+/// A named source of items, see --modes
+///
+/// Every mode has its own items, prompt, matching, and way of disposing of
+/// the selection, so modes which would otherwise override each other can be
+/// used in one session. Ctrl-Tab and Ctrl-Shift-Tab cycle through them.
+pub trait Mode: Debug {
+    fn name(&self) -> &str;
+
+    /// Shown instead of the configured prompt
+    fn prompt(&self) -> Option<String> {
+	None
+    }
+
+    /// Called the first time the mode is switched to
+    fn items(&mut self, _drw: &mut Drw) -> CompResult<Vec<Item>> {
+	Ok(Vec::new())
+    }
+
+    /// Like Drw::gen_matches, but without compiled in plugins
+    fn gen_matches(&mut self, drw: &mut Drw) -> CompResult<Vec<Item>> {
+	drw.builtin_matches()
+    }
+
+    /// Like Drw::dispose, returns true if the program should exit
+    fn dispose(&mut self, _drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	println!("{}", output);
+	Ok(recommendation)
+    }
+
+    /// A file descriptor which becomes readable when there's something new to draw
+    fn wakeup(&self) -> Option<c_int> {
+	None
+    }
+}
+
+/// Items from stdin, handled by the hooks in plugin_entry.rs like without --modes
+#[derive(Debug)]
+pub struct Dmenu;
+
+impl Mode for Dmenu {
+    fn name(&self) -> &str {
+	"dmenu"
+    }
+    fn gen_matches(&mut self, drw: &mut Drw) -> CompResult<Vec<Item>> {
+	drw.gen_matches()
+    }
+    fn dispose(&mut self, drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	drw.dispose(output, recommendation)
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    mode: Option<Box<dyn Mode>>, // None while it's being called
+    items: Option<Items>, // None until first switched to, and while active
+}
+
+/// The modes given with --modes, empty without it
+#[derive(Debug, Default)]
+pub struct Modes {
+    slots: Vec<Slot>,
+    active: usize,
+}
+
+impl Modes {
+    /// Name of the active mode, if there are modes
+    pub fn active_name(&self) -> Option<&str> {
+	self.slots.get(self.active)?.mode.as_ref().map(|mode| mode.name())
+    }
+
+    /// Prompt of the active mode, if it has one
+    pub fn active_prompt(&self) -> Option<String> {
+	self.slots.get(self.active)?.mode.as_ref()?.prompt()
+    }
+
+    pub fn wakeup(&self) -> Option<c_int> {
+	self.slots.get(self.active)?.mode.as_ref()?.wakeup()
+    }
+
+    // false without modes, or while the active mode is being called
+    fn ready(&self) -> bool {
+	self.slots.get(self.active).map(|slot| slot.mode.is_some()).unwrap_or(false)
+    }
+}
+
+impl Drw {
+    /// Sets up the modes from --modes, and switches to the first.
+    /// Items already read from stdin go to the dmenu mode.
+    pub fn init_modes(&mut self) -> CompResult<()> {
+	if self.config.modes.is_empty() {
+	    return Ok(());
+	}
+	let stdin = self.items.take();
+	let mut slots = Vec::new();
+	for name in self.config.modes.clone() {
+	    let mode: Box<dyn Mode> = match name.as_str() {
+		"dmenu" => Box::new(Dmenu),
+		"ssh" => Box::new(Ssh),
+		"window" => Box::new(Windows::default()),
+		"drun" => Box::new(Drun::default()),
+		"run" => Box::new(Executables),
+		_ => if let Some(i) = self.plugins.iter().position(|p| p.name() == name) {
+		    // it's only consulted in its own mode from now on
+		    Box::new(PluginMode::new(self.plugins.remove(i)))
+		} else if let Some(sep) = name.find(':') {
+		    Box::new(CmdMode::spawn(&name[..sep], &name[sep+1..])?)
+		} else {
+		    return Die::stderr(format!("--modes: unknown mode '{}'", name));
+		},
+	    };
+	    slots.push(Slot{mode: Some(mode), items: None});
+	}
+	let dmenu = slots.iter().position(|slot| slot.mode.as_ref().unwrap().name() == "dmenu");
+	if let Some(i) = dmenu {
+	    slots[i].items = stdin;
+	}
+	self.modes.slots = slots;
+	self.activate_mode(0)
+    }
+
+    /// Cycles to the next mode, or the previous one if !forward
+    pub fn switch_mode(&mut self, forward: bool) -> CompResult<()> {
+	let len = self.modes.slots.len();
+	if len < 2 {
+	    return Ok(());
+	}
+	let active = self.modes.active;
+	self.modes.slots[active].items = self.items.take();
+	self.activate_mode(if forward {(active+1)%len} else {(active+len-1)%len})?;
+	self.draw()
+    }
+
+    fn activate_mode(&mut self, index: usize) -> CompResult<()> {
+	self.modes.active = index;
+	let mut items = match self.modes.slots[index].items.take() {
+	    Some(items) => items,
+	    None => Items::new(self.with_mode(|mode, drw| mode.items(drw))?),
+	};
+	items.curr = 0;
+	self.items = Some(items);
+	Ok(())
+    }
+
+    /// gen_matches of the active mode
+    pub fn mode_gen_matches(&mut self) -> CompResult<Vec<Item>> {
+	if self.modes.ready() {
+	    self.with_mode(|mode, drw| mode.gen_matches(drw))
+	} else {
+	    self.gen_matches()
+	}
+    }
+
+    /// dispose of the active mode
+    pub fn mode_dispose(&mut self, output: String, recommendation: bool) -> CompResult<bool> {
+	if self.modes.ready() {
+	    self.with_mode(|mode, drw| mode.dispose(drw, output, recommendation))
+	} else {
+	    self.dispose(output, recommendation)
+	}
+    }
+
+    // The mode is taken out for the duration, so it can have the rest of self
+    fn with_mode<T>(&mut self, f: impl FnOnce(&mut Box<dyn Mode>, &mut Drw) -> T) -> T {
+	let active = self.modes.active;
+	let mut mode = self.modes.slots[active].mode.take().unwrap();
+	let ret = f(&mut mode, self);
+	self.modes.slots[active].mode = Some(mode);
+	ret
+    }
+}
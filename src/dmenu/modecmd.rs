@@ -0,0 +1,243 @@
+use std::io::Write;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use libc::{c_int, poll, pollfd, POLLIN};
+use serde_json::{json, Value};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::drw::Drw;
+use crate::item::{Item, Partition};
+use crate::mode::Mode;
+use crate::util::LineReader;
+use crate::result::*;
+
+/// How long to wait on the helper before drawing what it sent last
+const TIMEOUT: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum Action {
+    Dispose(String), // as if an item with this output was selected
+    Input(String), // replace the input
+    Exit, // like Escape
+}
+
+/// A helper process which supplies items, see --mode-cmd
+///
+/// Every time the input or cursor changes, a line of JSON is written to its stdin:
+/// `{"input": "...", "cursor": 0, "selected": "..." or null}`
+/// It answers each with a line of JSON:
+/// `{"items": [...], "prompt": "...", "action": {...}}`
+/// where items are strings or `{"text": "...", "output": "..."}` objects, and the
+/// optional action is `{"type": "dispose", "output": "..."}`,
+/// `{"type": "input", "input": "..."}` or `{"type": "exit"}`.
+/// A missing prompt keeps the current one.
+///
+/// If the helper takes longer than TIMEOUT the previous items stay up until it answers.
+/// If it dies, they stay up for good.
+#[derive(Debug)]
+pub struct ModeCmd {
+    command: String,
+    child: Child,
+    stdin: ChildStdin,
+    pub reader: Option<LineReader>, // None once the helper has exited
+    sent: Option<(String, usize)>, // input and cursor of the last request
+    pending: usize, // requests not answered yet
+    pub items: Vec<(String, Option<String>)>, // text and output
+    pub prompt: Option<String>,
+    action: Option<Action>,
+}
+
+impl ModeCmd {
+    pub fn spawn(command: &str) -> CompResult<Self> {
+	let mut child = Command::new("sh").arg("-c").arg(command)
+	    .stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()
+	    .map_err(|err| Die::Stderr(format!("--mode-cmd: could not run '{}': {}", command, err)))?;
+	let stdin = child.stdin.take().unwrap();
+	let reader = LineReader::spawn(child.stdout.take().unwrap())?;
+	Ok(Self{command: command.to_string(), child, stdin, reader: Some(reader), sent: None,
+		pending: 0, items: Vec::new(), prompt: None, action: None})
+    }
+
+    /// Sends a request if input or cursor changed since the last one,
+    /// then waits up to TIMEOUT for answers. Returns the action asked for, if any
+    pub fn query(&mut self, input: &str, cursor: usize, selected: Option<&str>) -> Option<Action> {
+	self.wait(input, cursor, selected);
+	self.action.take()
+    }
+
+    fn wait(&mut self, input: &str, cursor: usize, selected: Option<&str>) {
+	if self.reader.is_none() {
+	    return;
+	}
+	if self.sent.as_ref().map(|(i, c)| (i.as_str(), *c)) != Some((input, cursor)) {
+	    let request = json!({"input": input, "cursor": cursor, "selected": selected});
+	    if writeln!(self.stdin, "{}", request).and_then(|_| self.stdin.flush()).is_err() {
+		return self.hang_up();
+	    }
+	    self.sent = Some((input.to_string(), cursor));
+	    self.pending += 1;
+	}
+	let deadline = Instant::now() + TIMEOUT;
+	loop {
+	    let (lines, finished, wakeup) = match self.reader.as_mut() {
+		Some(reader) => match reader.recv() {
+		    Ok((lines, finished)) => (lines, finished, reader.wakeup),
+		    Err(err) => {
+			eprintln!("--mode-cmd: cannot read from '{}': {}", self.command, err);
+			self.reader = None;
+			return;
+		    },
+		},
+		None => return,
+	    };
+	    for line in lines {
+		self.pending = self.pending.saturating_sub(1);
+		self.receive(&line);
+	    }
+	    if finished {
+		return self.hang_up();
+	    }
+	    let now = Instant::now();
+	    if self.pending == 0 || now >= deadline {
+		return;
+	    }
+	    let mut fd = pollfd{fd: wakeup, events: POLLIN, revents: 0};
+	    unsafe {
+		poll(&mut fd, 1, (deadline - now).as_millis() as i32 + 1);
+	    }
+	}
+    }
+
+    fn receive(&mut self, line: &str) {
+	let response: Value = match serde_json::from_str(line) {
+	    Ok(response) => response,
+	    Err(err) => {
+		eprintln!("--mode-cmd: invalid response: {}", err);
+		return;
+	    },
+	};
+	if let Some(items) = response["items"].as_array() {
+	    self.items = items.iter().filter_map(|item| match item {
+		Value::String(text) => Some((text.clone(), None)),
+		_ => item["text"].as_str().map(|text| {
+		    (text.to_string(), item["output"].as_str().map(|o| o.to_string()))
+		}),
+	    }).collect();
+	}
+	if let Some(prompt) = response["prompt"].as_str() {
+	    self.prompt = Some(prompt.to_string());
+	}
+	let action = &response["action"];
+	self.action = if self.pending > 0 { // answers an outdated request
+	    None
+	} else {
+	    match action["type"].as_str() {
+		Some("dispose") => action["output"].as_str().map(|o| Action::Dispose(o.to_string())),
+		Some("input") => action["input"].as_str().map(|i| Action::Input(i.to_string())),
+		Some("exit") => Some(Action::Exit),
+		_ => None,
+	    }
+	};
+    }
+
+    fn hang_up(&mut self) {
+	if self.reader.take().is_some() {
+	    eprintln!("--mode-cmd: '{}' exited", self.command);
+	}
+    }
+}
+
+impl Drop for ModeCmd {
+    fn drop(&mut self) {
+	let _ = self.child.kill();
+	let _ = self.child.wait();
+    }
+}
+
+/// A helper process as a mode, given to --modes as NAME:PROG
+/// Selections are printed, as without --modes.
+#[derive(Debug)]
+pub struct CmdMode {
+    name: String,
+    cmd: ModeCmd,
+}
+
+impl CmdMode {
+    pub fn spawn(name: &str, command: &str) -> CompResult<Self> {
+	Ok(Self{name: name.to_string(), cmd: ModeCmd::spawn(command)?})
+    }
+}
+
+impl Mode for CmdMode {
+    fn name(&self) -> &str {
+	&self.name
+    }
+    fn prompt(&self) -> Option<String> {
+	self.cmd.prompt.clone()
+    }
+    fn gen_matches(&mut self, drw: &mut Drw) -> CompResult<Vec<Item>> {
+	let selected = drw.selected_text();
+	let action = self.cmd.query(&drw.input, drw.pseudo_globals.cursor, selected.as_ref().map(|s| s.as_str()));
+	if let Some(output) = drw.helper_action(action)? {
+	    if self.dispose(drw, output, true)? {
+		return Die::stdout(String::new());
+	    }
+	}
+	Ok(helper_items(self.cmd.items.clone()))
+    }
+    fn wakeup(&self) -> Option<c_int> {
+	self.cmd.reader.as_ref().map(|reader| reader.wakeup)
+    }
+}
+
+impl Drw {
+    /// Items from the --mode-cmd helper, carrying out any action it asked for
+    pub fn mode_cmd_matches(&mut self) -> CompResult<Vec<Item>> {
+	let selected = self.selected_text();
+	let cmd = self.mode_cmd.as_mut().unwrap();
+	let action = cmd.query(&self.input, self.pseudo_globals.cursor, selected.as_ref().map(|s| s.as_str()));
+	let entries = cmd.items.clone();
+	if let Some(output) = self.helper_action(action)? {
+	    if self.dispose(output, true)? {
+		return Die::stdout(String::new());
+	    }
+	}
+	Ok(helper_items(entries))
+    }
+
+    /// Text of the highlighted item, if there is one
+    pub fn selected_text(&self) -> Option<String> {
+	let items = self.items.as_ref().unwrap();
+	if items.cached_partitions.len() > 0 {
+	    let (partition_i, partition) = Partition::decompose(&items.cached_partitions, self);
+	    Some(items.cached_partitions[partition][partition_i].text.to_string())
+	} else {
+	    None
+	}
+    }
+
+    /// Carries out an action asked for by a helper.
+    /// Returns the output to dispose of, which is left to the caller
+    pub fn helper_action(&mut self, action: Option<Action>) -> CompResult<Option<String>> {
+	match action {
+	    Some(Action::Dispose(output)) => return Ok(Some(output)),
+	    Some(Action::Input(input)) => {
+		self.input = input;
+		self.pseudo_globals.cursor = self.input.graphemes(true).count();
+	    },
+	    Some(Action::Exit) => return Die::stderr(String::new()),
+	    None => {},
+	}
+	Ok(None)
+    }
+}
+
+/// Turns a helper's text and output pairs into items
+fn helper_items(entries: Vec<(String, Option<String>)>) -> Vec<Item> {
+    entries.into_iter().map(|(text, output)| {
+	let mut item = Item::new(text, false);
+	item.payload = output.map(Arc::new);
+	item
+    }).collect()
+}
@@ -0,0 +1,433 @@
+use std::env;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use clap::Arg;
+use serde_json::{json, Value};
+use toml::value::Table;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::clapflags::CLAP_FLAGS;
+use crate::conffile;
+use crate::config::Config;
+use crate::drw::Drw;
+use crate::dylib::DylibPlugin;
+use crate::item::Item;
+use crate::mode::Mode;
+use crate::util::xdg_dir;
+use crate::result::*;
+
+/// Version of the request/response protocol, sent with every describe request
+pub const PROTOCOL_VERSION: u64 = 1;
+
+// Hooks a plugin lists in describe to be sent them. init and action always are.
+const HOOKS: [&str; 5] = ["format_stdin", "format_input", "gen_matches", "postprocess_matches", "dispose"];
+
+/// A command line flag declared by a runtime plugin
+#[derive(Debug)]
+pub struct PluginArg {
+    pub name: String,
+    pub long: String,
+    pub help: String,
+    pub takes_value: bool,
+}
+
+/// An entry returned from gen_matches
+pub enum Match {
+    Item(usize), // index into the items read from stdin
+    Text(String), // generated on the fly
+}
+
+lazy_static::lazy_static! {
+    // Flags of loaded plugins by plugin index, picked up when CLAP_FLAGS is built
+    static ref ARGS: Mutex<Vec<(usize, PluginArg)>> = Mutex::new(Vec::new());
+    // Actions of loaded plugins as PLUGIN.ACTION, which keys can be bound to
+    static ref ACTIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    // Hooks of loaded plugins as PLUGIN.HOOK, which are the only ones sent
+    static ref HANDLED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    // Config defaults of loaded plugins by plugin name, applied by apply_defaults
+    static ref DEFAULTS: Mutex<Vec<(String, Table)>> = Mutex::new(Vec::new());
+}
+
+/// A plugin loaded at runtime, as opposed to compiled in through PLUGINS in config.mk
+/// The hooks mirror those in plugin_entry.rs, and are called before the built-in ones.
+///
+/// Plugins speak JSON: every hook sends a request object to `call` and interprets the
+/// response. A null response means the hook isn't handled, in which case the next
+/// plugin is asked, and finally the built-in behavior is used.
+/// See src/plugins/README.md for the message format.
+pub trait Plugin: Debug {
+    fn name(&self) -> &str;
+    fn call(&mut self, request: Value) -> CompResult<Value>;
+
+    /// Flags to register with clap, names of actions keys can be bound to,
+    /// hooks it handles, and config defaults in config file syntax
+    fn describe(&mut self) -> CompResult<(Vec<PluginArg>, Vec<String>, Vec<String>, Table)> {
+	let response = self.call(json!({"hook": "describe", "version": PROTOCOL_VERSION}))?;
+	let mut args = Vec::new();
+	for arg in response["args"].as_array().into_iter().flatten() {
+	    let name = match arg["name"].as_str() {
+		Some(name) => name.to_string(),
+		None => return Err(self.die("describe")),
+	    };
+	    args.push(PluginArg{
+		long: arg["long"].as_str().map(|l| l.to_string()).unwrap_or_else(|| name.clone()),
+		help: arg["help"].as_str().unwrap_or("").to_string(),
+		takes_value: arg["takes_value"].as_bool().unwrap_or(false),
+		name,
+	    });
+	}
+	let actions = match response["actions"] {
+	    Value::Null => Vec::new(),
+	    _ => strings(&response["actions"]).ok_or_else(|| self.die("describe"))?,
+	};
+	let hooks = match response["hooks"] {
+	    Value::Null => Vec::new(),
+	    _ => strings(&response["hooks"])
+		.filter(|hooks| hooks.iter().all(|hook| HOOKS.contains(&hook.as_str())))
+		.ok_or_else(|| self.die("describe"))?,
+	};
+	let config = match to_toml(&response["config"]) {
+	    Some(toml::Value::Table(table)) => table,
+	    _ if response["config"].is_null() => Table::new(),
+	    _ => return Err(self.die("describe")),
+	};
+	Ok((args, actions, hooks, config))
+    }
+
+    /// Whether describe listed hook, as only those are sent
+    fn handles(&self, hook: &str) -> bool {
+	HANDLED.lock().unwrap().iter().any(|handled| *handled == format!("{}.{}", self.name(), hook))
+    }
+
+    /// Called once command line flags are parsed, with the values of this plugin's flags
+    fn init(&mut self, flags: Value) -> CompResult<()> {
+	self.call(json!({"hook": "init", "flags": flags})).map(|_| ())
+    }
+
+    fn format_stdin(&mut self, lines: &[String]) -> CompResult<Option<Vec<String>>> {
+	if !self.handles("format_stdin") {
+	    return Ok(None);
+	}
+	let response = self.call(json!({"hook": "format_stdin", "lines": lines}))?;
+	match response {
+	    Value::Null => Ok(None),
+	    _ => strings(&response["lines"]).map(Some).ok_or_else(|| self.die("format_stdin")),
+	}
+    }
+
+    fn format_input(&mut self, input: &str) -> CompResult<Option<String>> {
+	if !self.handles("format_input") {
+	    return Ok(None);
+	}
+	let response = self.call(json!({"hook": "format_input", "input": input}))?;
+	match response {
+	    Value::Null => Ok(None),
+	    _ => response["input"].as_str().map(|i| Some(i.to_string()))
+		.ok_or_else(|| self.die("format_input")),
+	}
+    }
+
+    fn gen_matches(&mut self, input: &str, items: &[Item]) -> CompResult<Option<Vec<Match>>> {
+	if !self.handles("gen_matches") {
+	    return Ok(None);
+	}
+	let texts: Vec<&String> = items.iter().map(|item| &*item.text).collect();
+	let response = self.call(json!({"hook": "gen_matches", "input": input, "items": texts}))?;
+	if response.is_null() {
+	    return Ok(None);
+	}
+	let mut matches = Vec::new();
+	for entry in response["matches"].as_array().ok_or_else(|| self.die("gen_matches"))? {
+	    matches.push(match entry {
+		Value::Number(n) => match n.as_u64().map(|i| i as usize).filter(|i| *i < items.len()) {
+		    Some(i) => Match::Item(i),
+		    None => return Err(self.die("gen_matches")),
+		},
+		Value::String(text) => Match::Text(text.clone()),
+		_ => return Err(self.die("gen_matches")),
+	    });
+	}
+	Ok(Some(matches))
+    }
+
+    /// Returns the order to show matches in, as indices into matches
+    fn postprocess_matches(&mut self, input: &str, matches: &[Item]) -> CompResult<Option<Vec<usize>>> {
+	if !self.handles("postprocess_matches") {
+	    return Ok(None);
+	}
+	let texts: Vec<&String> = matches.iter().map(|item| &*item.text).collect();
+	let response = self.call(json!({"hook": "postprocess_matches", "input": input, "matches": texts}))?;
+	if response.is_null() {
+	    return Ok(None);
+	}
+	response["matches"].as_array()
+	    .and_then(|order| order.iter()
+		      .map(|i| i.as_u64().map(|i| i as usize).filter(|i| *i < matches.len()))
+		      .collect::<Option<Vec<usize>>>())
+	    .map(Some).ok_or_else(|| self.die("postprocess_matches"))
+    }
+
+    /// Returns whether to exit if the output was taken care of
+    fn dispose(&mut self, output: &str, recommendation: bool) -> CompResult<Option<bool>> {
+	if !self.handles("dispose") {
+	    return Ok(None);
+	}
+	let response = self.call(json!({"hook": "dispose", "output": output, "exit": recommendation}))?;
+	match response {
+	    Value::Null => Ok(None),
+	    _ => response["exit"].as_bool().map(Some).ok_or_else(|| self.die("dispose")),
+	}
+    }
+
+    /// Runs one of the actions from describe, returning the new input if it changed
+    /// and whether to exit
+    fn action(&mut self, action: &str, input: &str) -> CompResult<(Option<String>, bool)> {
+	let response = self.call(json!({"hook": "action", "action": action, "input": input}))?;
+	if response.is_null() {
+	    return Ok((None, false));
+	}
+	let input = match &response["input"] {
+	    Value::Null => None,
+	    Value::String(input) => Some(input.clone()),
+	    _ => return Err(self.die("action")),
+	};
+	match response["exit"] {
+	    Value::Null => Ok((input, false)),
+	    Value::Bool(exit) => Ok((input, exit)),
+	    _ => Err(self.die("action")),
+	}
+    }
+
+    fn die(&self, hook: &str) -> Die {
+	Die::Stderr(format!("plugin {}: invalid response to {}", self.name(), hook))
+    }
+}
+
+/// Loads the plugins asked for with --plugin, or `plugins` in the config file.
+/// Their flags are registered with clap, so this has to run before CLAP_FLAGS is used,
+/// which includes Config::default when a compiled in plugin overrides ConfigDefault
+/// with a flag.
+pub fn load() -> CompResult<Vec<Box<dyn Plugin>>> {
+    let mut plugins: Vec<Box<dyn Plugin>> = Vec::new();
+    for name in requested() {
+	let mut plugin: Box<dyn Plugin> = Box::new(DylibPlugin::open(&name, &search_path())?);
+	let (args, actions, hooks, defaults) = plugin.describe()?;
+	ACTIONS.lock().unwrap().extend(actions.into_iter()
+				       .map(|action| format!("{}.{}", plugin.name(), action)));
+	HANDLED.lock().unwrap().extend(hooks.into_iter()
+				       .map(|hook| format!("{}.{}", plugin.name(), hook)));
+	DEFAULTS.lock().unwrap().push((plugin.name().to_string(), defaults));
+	ARGS.lock().unwrap().extend(args.into_iter().map(|arg| (plugins.len(), arg)));
+	plugins.push(plugin);
+    }
+    Ok(plugins)
+}
+
+/// Applies the config defaults of loaded plugins, before the config file is loaded
+pub fn apply_defaults(config: &mut Config) -> CompResult<()> {
+    for (name, defaults) in DEFAULTS.lock().unwrap().iter() {
+	conffile::apply(config, defaults, &format!("{}.", name))?;
+    }
+    Ok(())
+}
+
+/// Hands every plugin the values of its flags, as {"name": true} for flags
+/// and {"name": "value"} for options. Flags not passed are left out.
+pub fn init(plugins: &mut [Box<dyn Plugin>]) -> CompResult<()> {
+    let matches = &*CLAP_FLAGS; // builds with ARGS, so before locking it
+    let args = ARGS.lock().unwrap();
+    for (i, plugin) in plugins.iter_mut().enumerate() {
+	let mut flags = serde_json::Map::new();
+	for (_, arg) in args.iter().filter(|(owner, _)| *owner == i) {
+	    if let Some(value) = matches.value_of(&arg.name) {
+		flags.insert(arg.name.clone(), json!(value));
+	    } else if matches.is_present(&arg.name) {
+		flags.insert(arg.name.clone(), json!(true));
+	    }
+	}
+	plugin.init(Value::Object(flags))?;
+    }
+    Ok(())
+}
+
+/// Whether a loaded plugin declared name, as PLUGIN.ACTION
+pub fn has_action(name: &str) -> bool {
+    ACTIONS.lock().unwrap().iter().any(|action| action == name)
+}
+
+/// Flags declared by loaded plugins, for CLAP_FLAGS
+pub fn clap_args() -> Vec<Arg<'static, 'static>> {
+    // clap wants these to live forever, and they do: they're parsed once
+    fn leak(s: &str) -> &'static str {
+	Box::leak(s.to_string().into_boxed_str())
+    }
+    ARGS.lock().unwrap().iter().map(|(_, arg)| {
+	Arg::with_name(leak(&arg.name))
+	    .long(leak(&arg.long))
+	    .help(leak(&arg.help))
+	    .takes_value(arg.takes_value)
+    }).collect()
+}
+
+// CLAP_FLAGS can't be used yet, so --plugin is picked out of the arguments by hand
+fn requested() -> Vec<String> {
+    let mut names = conffile::plugins();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+	if arg == "--plugin" {
+	    names.extend(args.next());
+	} else if let Some(name) = arg.strip_prefix("--plugin=") {
+	    names.push(name.to_string());
+	}
+    }
+    names
+}
+
+/// Directories runtime plugins are looked up in: $DMENU_PLUGIN_PATH if set,
+/// otherwise $XDG_DATA_HOME/dmenu-rs/plugins and then the system wide ones
+pub fn search_path() -> Vec<PathBuf> {
+    match env::var_os("DMENU_PLUGIN_PATH") {
+	Some(path) => env::split_paths(&path).collect(),
+	None => xdg_dir("XDG_DATA_HOME", ".local/share")
+	    .map(|dir| dir.join("dmenu-rs").join("plugins")).into_iter()
+	    .chain(vec![PathBuf::from("/usr/local/lib/dmenu-rs/plugins"),
+			PathBuf::from("/usr/lib/dmenu-rs/plugins")])
+	    .collect(),
+    }
+}
+
+fn strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(|s| s.as_str().map(|s| s.to_string())).collect()
+}
+
+fn to_toml(value: &Value) -> Option<toml::Value> {
+    Some(match value {
+	Value::Bool(b) => toml::Value::Boolean(*b),
+	Value::Number(n) => match n.as_i64() {
+	    Some(i) => toml::Value::Integer(i),
+	    None => toml::Value::Float(n.as_f64()?),
+	},
+	Value::String(s) => toml::Value::String(s.clone()),
+	Value::Array(a) => toml::Value::Array(a.iter().map(to_toml).collect::<Option<_>>()?),
+	Value::Object(o) => toml::Value::Table(o.iter()
+					       .map(|(k, v)| to_toml(v).map(|v| (k.clone(), v)))
+					       .collect::<Option<_>>()?),
+	Value::Null => return None,
+    })
+}
+
+/// A runtime plugin named in --modes, which only handles its own mode
+#[derive(Debug)]
+pub struct PluginMode {
+    plugin: Box<dyn Plugin>,
+}
+
+impl PluginMode {
+    pub fn new(plugin: Box<dyn Plugin>) -> Self {
+	Self{plugin}
+    }
+}
+
+impl Mode for PluginMode {
+    fn name(&self) -> &str {
+	self.plugin.name()
+    }
+    fn gen_matches(&mut self, drw: &mut Drw) -> CompResult<Vec<Item>> {
+	match self.plugin.gen_matches(&drw.input, drw.get_items())? {
+	    Some(matches) => Ok(drw.plugin_matches(matches)),
+	    None => drw.builtin_matches(),
+	}
+    }
+    fn dispose(&mut self, _drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	match self.plugin.dispose(&output, recommendation)? {
+	    Some(exit) => Ok(exit),
+	    None => {
+		println!("{}", output);
+		Ok(recommendation)
+	    },
+	}
+    }
+}
+
+// The built-in hooks in plugin_entry.rs call these first
+impl Drw {
+    pub fn plugins_format_stdin(&mut self, mut lines: Vec<String>) -> CompResult<Vec<String>> {
+	for plugin in self.plugins.iter_mut() {
+	    if let Some(formatted) = plugin.format_stdin(&lines)? {
+		lines = formatted;
+	    }
+	}
+	Ok(lines)
+    }
+
+    pub fn plugins_format_input(&mut self, mut input: String) -> CompResult<String> {
+	for plugin in self.plugins.iter_mut() {
+	    if let Some(formatted) = plugin.format_input(&input)? {
+		input = formatted;
+	    }
+	}
+	Ok(input)
+    }
+
+    pub fn plugins_gen_matches(&mut self) -> CompResult<Option<Vec<Item>>> {
+	let items = &self.items.as_ref().unwrap().data;
+	let mut found = None;
+	for plugin in self.plugins.iter_mut() {
+	    found = plugin.gen_matches(&self.input, items)?;
+	    if found.is_some() {
+		break;
+	    }
+	}
+	match found {
+	    Some(matches) => Ok(Some(self.plugin_matches(matches))),
+	    None => Ok(None),
+	}
+    }
+
+    fn plugin_matches(&self, matches: Vec<Match>) -> Vec<Item> {
+	matches.into_iter().map(|m| match m {
+	    Match::Item(i) => self.get_items()[i].clone(),
+	    Match::Text(text) => Item::new(text, false),
+	}).collect()
+    }
+
+    pub fn plugins_postprocess_matches(&mut self, mut items: Vec<Item>) -> CompResult<Vec<Item>> {
+	for plugin in self.plugins.iter_mut() {
+	    if let Some(order) = plugin.postprocess_matches(&self.input, &items)? {
+		items = order.into_iter().map(|i| items[i].clone()).collect();
+	    }
+	}
+	Ok(items)
+    }
+
+    /// Runs PLUGIN.ACTION, returning whether to exit
+    pub fn plugins_action(&mut self, name: &str) -> CompResult<bool> {
+	let (owner, action) = match name.rfind('.') {
+	    Some(dot) => (&name[..dot], &name[dot+1..]),
+	    None => return Ok(false),
+	};
+	let plugin = match self.plugins.iter_mut().find(|plugin| plugin.name() == owner) {
+	    Some(plugin) => plugin,
+	    None => return Ok(false), // moved into its mode by --modes
+	};
+	let (input, exit) = plugin.action(action, &self.input)?;
+	if let Some(input) = input {
+	    self.pseudo_globals.cursor = input.graphemes(true).count();
+	    self.input = input;
+	    self.items.as_mut().unwrap().curr = 0;
+	}
+	self.draw()?;
+	Ok(exit)
+    }
+
+    /// Some(exit) if a plugin took care of the output
+    pub fn plugins_dispose(&mut self, output: &str, recommendation: bool) -> CompResult<Option<bool>> {
+	for plugin in self.plugins.iter_mut() {
+	    if let Some(exit) = plugin.dispose(output, recommendation)? {
+		return Ok(Some(exit));
+	    }
+	}
+	Ok(None)
+    }
+}
@@ -13,14 +13,19 @@ use regex::{Regex, RegexBuilder};
 use crate::config::DefaultWidth;
 use crate::config::Schemes::*;
 use crate::config::ConfigDefault;
+use crate::bindings::Bindings;
 
+/// Runtime plugins (see plugin.rs) are consulted by the defaults below.
+/// Overriding a method here also bypasses them.
 #[default]
 impl Drw {
     /**
      * When taking input from stdin, apply post-processing
+     * 
+     * With --incremental, this is called once for every chunk of lines read
      */
     pub fn format_stdin(&mut self, lines: Vec<String>) -> CompResult<Vec<String>> {
-	Ok(lines)
+	self.plugins_format_stdin(lines)
     }
     
     /**
@@ -28,7 +33,7 @@ impl Drw {
      * Does it need additional processing?
      */
     pub fn format_input(&mut self) -> CompResult<String> {
-	Ok(self.input.clone())
+	self.plugins_format_input(self.input.clone())
     }
 
     /**
@@ -41,6 +46,9 @@ impl Drw {
      * Returns - true if program should exit
      */
     pub fn dispose(&mut self, output: String, recommendation: bool) -> CompResult<bool> {
+	if let Some(exit) = self.plugins_dispose(&output, recommendation)? {
+	    return Ok(exit);
+	}
 	println!("{}", output);
 	Ok(recommendation)
     }
@@ -53,7 +61,7 @@ impl Drw {
      * but still allow a custom matching algorithm (such as from the fuzzy plugin)
      */
     pub fn postprocess_matches(&mut self, items: Vec<Item>) -> CompResult<Vec<Item>> {
-	Ok(items)
+	self.plugins_postprocess_matches(items)
     }
 
     /**
@@ -63,28 +71,13 @@ impl Drw {
      * Returns - Vector of items to be drawn
      */
     pub fn gen_matches(&mut self) -> CompResult<Vec<Item>> {
-	let re = RegexBuilder::new(&regex::escape(&self.input))
-	    .case_insensitive(!self.config.case_sensitive)
-	    .build().map_err(|_| Die::Stderr("Could not build regex".to_owned()))?;
-	let mut exact:     Vec<Item> = Vec::new();
-	let mut prefix:    Vec<Item> = Vec::new();
-	let mut substring: Vec<Item> = Vec::new();
-	for item in self.get_items() {
-	    match item.matches(&re) {
-		MatchCode::Exact => exact.push(item.clone()),
-		MatchCode::Prefix => prefix.push(item.clone()),
-		MatchCode::Substring => substring.push(item.clone()),
-		MatchCode::None => {}
-	    }
-	}
-	exact.reserve(prefix.len()+substring.len());
-	for item in prefix { // extend is broken for pointers
-	    exact.push(item);
+	if self.mode_cmd.is_some() {
+	    return self.mode_cmd_matches();
 	}
-	for item in substring {
-	    exact.push(item);
+	if let Some(matches) = self.plugins_gen_matches()? {
+	    return Ok(matches);
 	}
-	Ok(exact)
+	self.builtin_matches()
     }
 }
 
@@ -121,11 +114,62 @@ impl ConfigDefault {
 	arr[SchemeNorm as usize] = [*b"#bbbbbb\0", *b"#222222\0"];
 	arr[SchemeSel  as usize] = [*b"#eeeeee\0", *b"#005577\0"];
 	arr[SchemeOut  as usize] = [*b"#000000\0", *b"#00ffff\0"];
+	arr[SchemeMark as usize] = [*b"#eeeeee\0", *b"#770000\0"];
+	arr[SchemeNormHl as usize] = [*b"#ffc978\0", *b"#222222\0"];
+	arr[SchemeSelHl  as usize] = [*b"#ffc978\0", *b"#005577\0"];
 	arr
     }
     pub fn nostdin() -> bool {
 	false
     }
+    pub fn incremental() -> bool {
+	false
+    }
+    pub fn multi() -> bool {
+	false
+    }
+    pub fn delimiter() -> Option<String> {
+	None
+    }
+    pub fn display_field() -> usize {
+	1
+    }
+    pub fn output_field() -> Option<usize> {
+	None // print the whole line
+    }
+    pub fn history_key() -> Option<String> {
+	None
+    }
+    pub fn markup() -> bool {
+	false
+    }
+    pub fn mode_cmd() -> Option<String> {
+	None
+    }
+    pub fn modes() -> Vec<String> {
+	Vec::new() // just stdin
+    }
+    pub fn terminal() -> Option<String> {
+	None // $TERMINAL, or xterm
+    }
+    pub fn matching() -> String {
+	"tokens".to_owned() // like dmenu
+    }
+    pub fn backend() -> String {
+	"x11".to_owned()
+    }
+    pub fn render_to() -> Option<String> {
+	None // show the menu
+    }
+    pub fn replay() -> Option<String> {
+	None // $DMENU_KEYS, if set
+    }
+    pub fn bindings() -> Bindings {
+	Bindings::builtin()
+    }
+    pub fn vi() -> bool {
+	false
+    }
     pub fn render_minheight() -> u32 {
 	4
     }
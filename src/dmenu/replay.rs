@@ -0,0 +1,90 @@
+use x11::xlib::{ShiftMask, ControlMask, Mod1Mask};
+use std::{collections::VecDeque, env, fs};
+
+use crate::backend::Event;
+use crate::bindings::parse_key;
+use crate::config::Config;
+use crate::result::*;
+
+/// Keys to press before any real input, from --replay or $DMENU_KEYS.
+///
+/// A script is a list of whitespace separated keys, named as in --bind,
+/// such as Tab, C-w or S-Return. S- on a letter types it upper case.
+/// Text in double quotes is typed as is, and # starts a comment.
+pub fn load(config: &Config) -> CompResult<VecDeque<Event>> {
+    match config.replay.as_ref() {
+	Some(path) => {
+	    let script = fs::read_to_string(path)
+		.map_err(|err| Die::Stderr(format!("--replay: cannot read {}: {}", path, err)))?;
+	    parse(&script).map_err(|err| Die::Stderr(format!("--replay: {}", err)))
+	},
+	None => match env::var("DMENU_KEYS") {
+	    Ok(script) => parse(&script).map_err(|err| Die::Stderr(format!("$DMENU_KEYS: {}", err))),
+	    Err(_) => Ok(VecDeque::new()),
+	},
+    }
+}
+
+fn parse(script: &str) -> Result<VecDeque<Event>, String> {
+    let mut events = VecDeque::new();
+    for line in script.lines() {
+	let mut rest = line.trim_start();
+	while rest.len() > 0 && !rest.starts_with('#') {
+	    if rest.starts_with('"') {
+		let end = rest[1..].find('"').ok_or_else(|| format!("unterminated text in '{}'", line))?;
+		events.push_back(Event::Key{ksym: 0, text: rest[1..end+1].to_string(), state: 0});
+		rest = &rest[end+2..];
+	    } else {
+		let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+		events.push_back(key(&rest[..end])?);
+		rest = &rest[end..];
+	    }
+	    rest = rest.trim_start();
+	}
+    }
+    Ok(events)
+}
+
+// A single key, such as C-w
+fn key(name: &str) -> Result<Event, String> {
+    let (mut ksym, state) = parse_key(name)?;
+    if state & ShiftMask != 0 { // X reports S-a as A
+	if let Some(upper) = typed(ksym).and_then(uppercase) {
+	    ksym = keysym(upper);
+	}
+    }
+    // only keys that type something without Control or Alt have text, as with XLookupString
+    let text = if state & (ControlMask | Mod1Mask) == 0 {
+	typed(ksym).map(|c| c.to_string()).unwrap_or_default()
+    } else {
+	String::new()
+    };
+    Ok(Event::Key{ksym, text, state})
+}
+
+// The char a keysym types: Latin-1 ones are their own code point,
+// and Unicode ones are offset by 0x01000000
+fn typed(ksym: u32) -> Option<char> {
+    match ksym {
+	0x20..=0x7e | 0xa0..=0xff => std::char::from_u32(ksym),
+	0x0100_0000..=0x0110_ffff => std::char::from_u32(ksym - 0x0100_0000),
+	_ => None,
+    }
+}
+
+// The keysym typing c, the reverse of typed
+fn keysym(c: char) -> u32 {
+    match c as u32 {
+	0x20..=0x7e | 0xa0..=0xff => c as u32,
+	code => code + 0x0100_0000,
+    }
+}
+
+// A lower case letter's upper case, if it's a single char
+fn uppercase(c: char) -> Option<char> {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+	(Some(u), None) if u != c => Some(u),
+	_ => None,
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::drw::Drw;
+use crate::item::Item;
+use crate::mode::Mode;
+use crate::util::spawn_in_terminal;
+use crate::result::*;
+
+/// Hosts from ~/.ssh/config and ~/.ssh/known_hosts, connected to in a terminal
+#[derive(Debug)]
+pub struct Ssh;
+
+impl Mode for Ssh {
+    fn name(&self) -> &str {
+	"ssh"
+    }
+    fn items(&mut self, _drw: &mut Drw) -> CompResult<Vec<Item>> {
+	Ok(hosts().into_iter().map(|host| Item::new(host, false)).collect())
+    }
+    fn dispose(&mut self, drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	spawn_in_terminal(drw, &["ssh", &output])?;
+	Ok(recommendation)
+    }
+}
+
+// In the order they're found, without duplicates
+fn hosts() -> Vec<String> {
+    let dir = match env::var_os("HOME") {
+	Some(home) => PathBuf::from(home).join(".ssh"),
+	None => return Vec::new(),
+    };
+    let mut hosts = config_hosts(&dir.join("config"));
+    hosts.extend(known_hosts(&dir.join("known_hosts")));
+    let mut seen = HashSet::new();
+    hosts.retain(|host| seen.insert(host.clone()));
+    hosts
+}
+
+// Host lines, leaving out patterns
+fn config_hosts(path: &Path) -> Vec<String> {
+    let config = fs::read_to_string(path).unwrap_or_default();
+    let mut hosts = Vec::new();
+    for line in config.lines() {
+	let mut words = line.split_whitespace();
+	if words.next().map(|w| w.eq_ignore_ascii_case("host")).unwrap_or(false) {
+	    hosts.extend(words.filter(|host| !host.contains(|c| c == '*' || c == '?' || c == '!'))
+			 .map(|host| host.to_string()));
+	}
+    }
+    hosts
+}
+
+// Hashed entries and ones on non-standard ports ([host]:port) are left out
+fn known_hosts(path: &Path) -> Vec<String> {
+    let known = fs::read_to_string(path).unwrap_or_default();
+    let mut hosts = Vec::new();
+    for line in known.lines() {
+	let field = match line.split_whitespace().next() {
+	    Some(field) if !field.starts_with(|c| c == '#' || c == '|' || c == '@') => field,
+	    _ => continue,
+	};
+	hosts.extend(field.split(',').filter(|host| !host.starts_with('['))
+		     .map(|host| host.to_string()));
+    }
+    hosts
+}
@@ -1,11 +1,13 @@
-use x11::xlib::{CurrentTime, RevertToParent, XSetInputFocus, XGetInputFocus, Window, Display,
-		GrabSuccess, GrabModeAsync, True, XDefaultRootWindow, XGrabKeyboard};
+use libc::{c_int, c_void, pipe, fcntl, read, write, close, F_SETFL, O_NONBLOCK};
 use crate::drw::Drw;
 use crate::item::Item;
-use std::mem::MaybeUninit;
-use std::time::Duration;
-use std::thread::sleep;
-use std::io::{self, BufRead};
+use crate::markup;
+use std::thread;
+use std::sync::{Arc, mpsc::{channel, Receiver, TryRecvError}};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::Command;
+use std::env;
 use crate::result::*;
 
 pub fn readstdin(drw: &mut Drw) -> CompResult<Vec<Item>> {
@@ -16,48 +18,162 @@ pub fn readstdin(drw: &mut Drw) -> CompResult<Vec<Item>> {
 	    Err(e) => return Die::stderr(format!("Could not read from stdin: {}", e)),
 	}
     }
+    lines_to_items(drw, lines)
+}
+
+// Runs lines through format_stdin and turns them into menu items
+pub fn lines_to_items(drw: &mut Drw, lines: Vec<String>) -> CompResult<Vec<Item>> {
     let mut ret = Vec::new();
     for line in drw.format_stdin(lines)?.into_iter() {
-	let item = Item::new(line, false, drw)?;
-	if item.width as i32 > drw.pseudo_globals.inputw {
-	    drw.pseudo_globals.inputw = item.width as i32;
+	let (text, payload, icon) = match drw.config.delimiter.as_ref() {
+	    Some(delimiter) => {
+		let (display, payload, icon) = split_fields(&line, delimiter,
+							    drw.config.display_field,
+							    drw.config.output_field);
+		(display, Some(payload), icon)
+	    },
+	    None => (line, None, None),
+	};
+	let (text, styles) = if drw.config.markup {
+	    markup::parse(&text)
+	} else {
+	    (text, Vec::new())
+	};
+	let mut item = Item::new(text, false);
+	item.payload = payload.map(Arc::new);
+	item.styles = Arc::new(styles);
+	if let Some(icon) = icon {
+	    item.set_icon(icon);
 	}
 	ret.push(item)
     }
     Ok(ret)
 }
 
-pub fn grabkeyboard(dpy: *mut Display, embed: Window) -> CompResult<()> {
-    let ts = Duration::from_millis(1);
+/// Reads lines on a background thread, such as stdin so the menu can be shown right away.
+/// Lines are handed over in chunks, and a byte is written to a pipe every
+/// time a chunk is ready so the X event loop can poll on it.
+/// A read error, such as invalid UTF-8, is handed over last.
+#[derive(Debug)]
+pub struct LineReader {
+    receiver: Receiver<io::Result<Vec<String>>>,
+    pub wakeup: c_int, // read end of the pipe
+}
 
-    if embed != 0 {
-	return Ok(());
+impl LineReader {
+    pub fn spawn<R: Read + Send + 'static>(source: R) -> CompResult<Self> {
+	let mut fds: [c_int; 2] = [0; 2];
+	unsafe {
+	    if pipe(fds.as_mut_ptr()) != 0 || fcntl(fds[0], F_SETFL, O_NONBLOCK) != 0 {
+		return Die::stderr("Could not create pipe".to_owned());
+	    }
+	}
+	let (sender, receiver) = channel();
+	let notify = fds[1];
+	thread::spawn(move || {
+	    let mut reader = BufReader::new(source);
+	    let mut chunk = Vec::new();
+	    loop {
+		let mut line = String::new();
+		let (done, error) = match reader.read_line(&mut line) {
+		    Ok(0) => (true, None),
+		    Err(err) => (true, Some(err)),
+		    Ok(_) => {
+			if line.ends_with('\n') {
+			    line.pop();
+			    if line.ends_with('\r') {
+				line.pop();
+			    }
+			}
+			chunk.push(line);
+			(false, None)
+		    },
+		};
+		// hand over whatever we have once the producer stalls
+		if done || reader.buffer().is_empty() || chunk.len() >= 4096 {
+		    if sender.send(Ok(std::mem::take(&mut chunk))).is_err() {
+			break;
+		    }
+		    if let Some(err) = error {
+			let _ = sender.send(Err(err));
+		    }
+		    unsafe {
+			write(notify, b"\0".as_ptr() as *const c_void, 1);
+		    }
+		}
+		if done {
+		    break;
+		}
+	    }
+	    drop(sender); // hang up, then wake the reader so it notices
+	    unsafe {
+		write(notify, b"\0".as_ptr() as *const c_void, 1);
+		close(notify);
+	    }
+	});
+	Ok(Self{receiver, wakeup: fds[0]})
     }
-    /* try to grab keyboard, we may have to wait for another process to ungrab */
-    for _ in 0..1000 {
-	if unsafe{XGrabKeyboard(dpy, XDefaultRootWindow(dpy), True, GrabModeAsync,
-				GrabModeAsync, CurrentTime) == GrabSuccess} {
-	    return Ok(());
+
+    /// Collects every chunk that's ready
+    /// Returns the lines, and whether the source is exhausted
+    pub fn recv(&mut self) -> io::Result<(Vec<String>, bool)> {
+	let mut buf = [0u8; 64];
+	while unsafe{read(self.wakeup, buf.as_mut_ptr() as *mut c_void, buf.len())} > 0 {}
+	let mut lines = Vec::new();
+	loop {
+	    match self.receiver.try_recv() {
+		Ok(chunk) => lines.append(&mut chunk?),
+		Err(TryRecvError::Empty) => return Ok((lines, false)),
+		Err(TryRecvError::Disconnected) => return Ok((lines, true)),
+	    }
 	}
-	sleep(ts);
     }
-    Die::stderr("cannot grab keyboard".to_owned())
 }
 
-pub fn grabfocus(drw: &Drw) -> CompResult<()> {
-    unsafe {
-	let ts = Duration::from_millis(1);
-	let mut focuswin: Window = MaybeUninit::uninit().assume_init();
-	let mut revertwin = MaybeUninit::uninit().assume_init();
+impl Drop for LineReader {
+    fn drop(&mut self) {
+	unsafe {
+	    close(self.wakeup);
+	}
+    }
+}
 
-	for _ in 0..100 {
-	    XGetInputFocus(drw.dpy, &mut focuswin, &mut revertwin);
-	    if focuswin == drw.pseudo_globals.win {
-		return Ok(());
-	    }
-	    XSetInputFocus(drw.dpy, drw.pseudo_globals.win, RevertToParent, CurrentTime);
-	    sleep(ts);
+// Splits a structured line into (display, payload, icon). Fields are 1-indexed,
+// missing fields are empty and no output field means the whole line.
+// A field starting with icon= is taken as the icon and doesn't count as a field.
+fn split_fields(line: &str, delimiter: &str, display_field: usize, output_field: Option<usize>) -> (String, String, Option<String>) {
+    let mut icon = None;
+    let fields: Vec<&str> = line.split(delimiter).filter(|f| {
+	if f.starts_with("icon=") {
+	    icon = Some(f["icon=".len()..].to_string());
+	    false
+	} else {
+	    true
 	}
-	Die::stderr("cannot grab focus".to_owned())
+    }).collect();
+    let field = |i: usize| fields.get(i-1).map(|f| f.to_string()).unwrap_or_default();
+    (field(display_field),
+     output_field.map(field).unwrap_or_else(|| fields.join(delimiter)),
+     icon)
+}
+
+/// Resolves an XDG base directory, falling back to $HOME/fallback
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+	Some(dir) if dir.len() > 0 => Some(PathBuf::from(dir)),
+	_ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
     }
 }
+
+/// Runs a command in --terminal, or $TERMINAL, or xterm, as `TERMINAL -e COMMAND...`
+pub fn spawn_in_terminal(drw: &Drw, command: &[&str]) -> CompResult<()> {
+    let terminal = drw.config.terminal.clone()
+	.or_else(|| env::var("TERMINAL").ok().filter(|t| t.len() > 0))
+	.unwrap_or_else(|| "xterm".to_string());
+    let mut words = terminal.split_whitespace();
+    let program = words.next()
+	.ok_or(Die::Stderr("--terminal: Terminal must not be empty".to_owned()))?;
+    Command::new(program).args(words).arg("-e").args(command).spawn()
+	.map_err(|err| Die::Stderr(format!("Could not run {}: {}", program, err)))?;
+    Ok(())
+}
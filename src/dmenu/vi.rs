@@ -0,0 +1,127 @@
+use x11::keysym::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::bindings::Action;
+use crate::drw::Drw;
+use crate::result::*;
+
+/// Where --vi style editing is at
+#[derive(Debug, Default)]
+pub struct Vi {
+    /// In normal mode, keys move around and edit the input instead of typing
+    pub normal: bool,
+    operator: Option<u32>, // d or c, waiting for a motion
+}
+
+// Words are separated by spaces, as for Ctrl-w and Ctrl-Left
+
+// Start of the next word, as vi's w
+fn next_word(graphemes: &[&str], mut i: usize) -> usize {
+    while i < graphemes.len() && graphemes[i] != " " {
+	i += 1;
+    }
+    while i < graphemes.len() && graphemes[i] == " " {
+	i += 1;
+    }
+    i
+}
+
+// Start of the word before i, as vi's b
+fn prev_word(graphemes: &[&str], mut i: usize) -> usize {
+    while i > 0 && graphemes[i-1] == " " {
+	i -= 1;
+    }
+    while i > 0 && graphemes[i-1] != " " {
+	i -= 1;
+    }
+    i
+}
+
+// Just past the end of the word at i, or of the spaces if i is on one, for cw
+fn word_end(graphemes: &[&str], mut i: usize) -> usize {
+    let space = i < graphemes.len() && graphemes[i] == " ";
+    while i < graphemes.len() && (graphemes[i] == " ") == space {
+	i += 1;
+    }
+    i
+}
+
+#[allow(non_upper_case_globals)]
+impl Drw {
+    /// Leaves insert mode, stepping back onto the last char typed like vi
+    pub fn normal_mode(&mut self) -> CompResult<bool> { // bool - should exit
+	self.vi.normal = true;
+	self.pseudo_globals.cursor = self.pseudo_globals.cursor.saturating_sub(1);
+	self.draw().map(|_| false)
+    }
+
+    /// Handles a key without Control or Alt in normal mode.
+    /// None if it isn't a normal mode key, so the bindings get it instead.
+    pub fn vi_keypress(&mut self, ksym: u32) -> CompResult<Option<bool>> { // bool - should exit
+	let graphemes: Vec<&str> = self.input.graphemes(true).collect();
+	let (len, cursor) = (graphemes.len(), self.pseudo_globals.cursor);
+	let last = len.saturating_sub(1); // the cursor stays on a char in normal mode
+	if let Some(operator) = self.vi.operator.take() {
+	    let (start, end) = match ksym {
+		XK_w if operator == XK_c => (cursor, word_end(&graphemes, cursor)),
+		XK_w => (cursor, next_word(&graphemes, cursor)),
+		_ if ksym == operator => (0, len), // dd or cc, the whole line
+		_ => return Ok(Some(false)), // anything else cancels, Escape included
+	    };
+	    self.delete_graphemes(start, end);
+	    if operator == XK_c {
+		self.vi.normal = false;
+	    } else {
+		self.pseudo_globals.cursor = start.min(self.input.graphemes(true).count().saturating_sub(1));
+	    }
+	    return self.draw().map(|_| Some(false));
+	}
+	self.pseudo_globals.cursor = match ksym {
+	    XK_Escape | XK_q => return self.act(Action::Exit).map(Some),
+	    XK_j => return self.act(Action::Down).map(Some),
+	    XK_k => return self.act(Action::Up).map(Some),
+	    XK_d | XK_c => {
+		self.vi.operator = Some(ksym);
+		return Ok(Some(false));
+	    },
+	    XK_i => {
+		self.vi.normal = false;
+		cursor
+	    },
+	    XK_a => {
+		self.vi.normal = false;
+		(cursor+1).min(len)
+	    },
+	    XK_I => {
+		self.vi.normal = false;
+		0
+	    },
+	    XK_A => {
+		self.vi.normal = false;
+		len
+	    },
+	    XK_h => cursor.saturating_sub(1),
+	    XK_l => (cursor+1).min(last),
+	    XK_w => next_word(&graphemes, cursor).min(last),
+	    XK_b => prev_word(&graphemes, cursor),
+	    XK_0 => 0,
+	    XK_dollar => last,
+	    XK_x => {
+		self.delete_graphemes(cursor, cursor+1);
+		cursor.min(self.input.graphemes(true).count().saturating_sub(1))
+	    },
+	    _ => return Ok(None),
+	};
+	self.draw().map(|_| Some(false))
+    }
+
+    // Removes the graphemes in start..end from the input, leaving the cursor at start
+    fn delete_graphemes(&mut self, start: usize, end: usize) {
+	self.input = self.input.graphemes(true).enumerate()
+	    .filter(|(i, _)| *i < start || *i >= end)
+	    .map(|(_, g)| g)
+	    .collect();
+	self.pseudo_globals.cursor = start;
+	self.items.as_mut().unwrap().curr = 0;
+    }
+}
@@ -0,0 +1,527 @@
+use wayland_client::{Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+		     delegate_noop, event_created_child,
+		     globals::{registry_queue_init, GlobalListContents},
+		     protocol::{wl_registry, wl_compositor::WlCompositor, wl_surface::WlSurface,
+				wl_shm::{self, WlShm}, wl_shm_pool::WlShmPool, wl_buffer::{self, WlBuffer},
+				wl_seat::{self, WlSeat}, wl_keyboard::{self, WlKeyboard},
+				wl_pointer::{self, WlPointer}, wl_output::WlOutput,
+				wl_data_device_manager::WlDataDeviceManager,
+				wl_data_device::{self, WlDataDevice}, wl_data_offer::WlDataOffer}};
+use wayland_protocols_wlr::layer_shell::v1::client::{zwlr_layer_shell_v1::{self, ZwlrLayerShellV1},
+						      zwlr_layer_surface_v1::{self, ZwlrLayerSurfaceV1, Anchor,
+									      KeyboardInteractivity}};
+use xkbcommon::xkb;
+use x11::xlib::{ShiftMask, ControlMask, Mod1Mask};
+use libc::{c_int, c_uint, c_void, memfd_create, ftruncate, mmap, munmap, pipe, poll, pollfd,
+	   MFD_CLOEXEC, PROT_READ, PROT_WRITE, MAP_SHARED, MAP_PRIVATE, MAP_FAILED, POLLIN};
+use std::{any::Any, collections::VecDeque, fmt, fs::File, io::{ErrorKind, Read}, ptr, slice};
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use crate::backend::{Backend, Color, Event};
+use crate::canvas::Canvas;
+use crate::config::Config;
+use crate::result::*;
+
+// How long to wait for the client owning the selection to hand it over
+const CLIPBOARD_TIMEOUT: Duration = Duration::from_millis(500);
+// How far to scroll for a page, about one wheel click. Touchpads take several events.
+const SCROLL_STEP: f64 = 10.0;
+
+/// A wlr-layer-shell surface, for sway and other wlroots based compositors.
+/// Drawing happens on a Canvas, which is copied to shared memory buffers when mapped.
+pub struct Wayland {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+}
+
+// Everything dispatched events get to change
+struct State {
+    compositor: WlCompositor,
+    shm: WlShm,
+    layer_shell: ZwlrLayerShellV1,
+    outputs: Vec<WlOutput>, // in the order they were announced, for --monitor
+    surface: Option<(WlSurface, ZwlrLayerSurfaceV1)>,
+    configured: Option<(u32, u32)>,
+    closed: bool,
+    canvas: Canvas,
+    buffers: Vec<Buffer>,
+    next_buffer: u32,
+    xkb: xkb::Context,
+    keymap: Option<(xkb::Keymap, xkb::State)>,
+    repeat: Option<(xkb::Keycode, Instant)>, // held key, and when it repeats next
+    repeat_info: (i32, i32), // rate per second, delay in milliseconds
+    pointer: (f64, f64),
+    scroll: f64, // scrolled since the last page flip, down is positive
+    _data_device: Option<WlDataDevice>, // kept for the selection events
+    selection: Option<WlDataOffer>,
+    events: VecDeque<Event>,
+}
+
+// A wl_buffer and the shared memory behind it
+struct Buffer {
+    id: u32,
+    buffer: WlBuffer,
+    pool: WlShmPool,
+    data: *mut u32,
+    w: usize,
+    h: usize,
+    busy: bool, // until the compositor releases it
+    _fd: OwnedFd,
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+	self.buffer.destroy();
+	self.pool.destroy();
+	unsafe {
+	    munmap(self.data as *mut c_void, self.w*self.h*4);
+	}
+    }
+}
+
+impl fmt::Debug for Wayland {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	f.debug_struct("Wayland")
+	    .field("canvas", &self.state.canvas)
+	    .field("configured", &self.state.configured)
+	    .finish()
+    }
+}
+
+impl Wayland {
+    pub fn open(_config: &Config) -> CompResult<Self> {
+	let conn = Connection::connect_to_env()
+	    .map_err(|err| Die::Stderr(format!("cannot connect to Wayland display: {}", err)))?;
+	let (globals, mut queue) = registry_queue_init::<State>(&conn)
+	    .map_err(|err| Die::Stderr(format!("cannot list Wayland globals: {}", err)))?;
+	let qh = queue.handle();
+	let missing = |name: &str| Die::Stderr(format!("the compositor doesn't support {}", name));
+	let compositor: WlCompositor = globals.bind(&qh, 4..=5, ()).map_err(|_| missing("wl_compositor"))?;
+	let shm: WlShm = globals.bind(&qh, 1..=1, ()).map_err(|_| missing("wl_shm"))?;
+	let layer_shell: ZwlrLayerShellV1 = globals.bind(&qh, 1..=4, ())
+	    .map_err(|_| missing("wlr-layer-shell"))?;
+	let seat: WlSeat = globals.bind(&qh, 1..=7, ()).map_err(|_| missing("wl_seat"))?;
+	let data_device = globals.bind::<WlDataDeviceManager, _, _>(&qh, 1..=3, ()).ok()
+	    .map(|manager| manager.get_data_device(&seat, &qh, ()));
+	let outputs = globals.contents().clone_list().into_iter()
+	    .filter(|global| global.interface == "wl_output")
+	    .map(|global| globals.registry().bind::<WlOutput, _, _>(global.name, global.version.min(4), &qh, ()))
+	    .collect();
+
+	let mut state = State{compositor, shm, layer_shell, outputs, surface: None, configured: None,
+			      closed: false, canvas: Canvas::default(), buffers: Vec::new(),
+			      next_buffer: 0, xkb: xkb::Context::new(xkb::CONTEXT_NO_FLAGS),
+			      keymap: None, repeat: None, repeat_info: (25, 600), pointer: (0.0, 0.0),
+			      scroll: 0.0, _data_device: data_device, selection: None, events: VecDeque::new()};
+	queue.roundtrip(&mut state) // for the seat's capabilities
+	    .map_err(|err| Die::Stderr(format!("Wayland roundtrip failed: {}", err)))?;
+	Ok(Self{conn, queue, state})
+    }
+
+    // Reads and dispatches whatever is on the socket, without blocking
+    fn dispatch(&mut self) -> CompResult<()> {
+	let _ = self.conn.flush();
+	if let Some(guard) = self.queue.prepare_read() {
+	    let _ = guard.read(); // WouldBlock if there's nothing to read
+	}
+	self.queue.dispatch_pending(&mut self.state)
+	    .map_err(|err| Die::Stderr(format!("Wayland dispatch failed: {}", err)))?;
+	if let Some((key, next)) = self.state.repeat {
+	    let now = Instant::now();
+	    if now >= next && self.state.repeat_info.0 > 0 {
+		let interval = Duration::from_millis(1000 / self.state.repeat_info.0 as u64);
+		self.state.repeat = Some((key, now + interval));
+		if let Some(event) = self.state.key_event(key) {
+		    self.state.events.push_back(event);
+		}
+	    }
+	}
+	Ok(())
+    }
+}
+
+impl State {
+    // Modifiers held, as an X modifier mask
+    fn mods(&self) -> c_uint {
+	let mut mask = 0;
+	if let Some((_, state)) = self.keymap.as_ref() {
+	    for (name, bit) in &[(xkb::MOD_NAME_SHIFT, ShiftMask), (xkb::MOD_NAME_CTRL, ControlMask),
+				 (xkb::MOD_NAME_ALT, Mod1Mask)] {
+		if state.mod_name_is_active(name, xkb::STATE_MODS_EFFECTIVE) {
+		    mask |= bit;
+		}
+	    }
+	}
+	mask
+    }
+
+    fn key_event(&self, key: xkb::Keycode) -> Option<Event> {
+	let (_, state) = self.keymap.as_ref()?;
+	Some(Event::Key{ksym: state.key_get_one_sym(key).raw(), text: state.key_get_utf8(key), state: self.mods()})
+    }
+
+    // Finds a released buffer of the current size, or makes one
+    fn buffer(&mut self, qh: &QueueHandle<Self>) -> Option<usize> {
+	let (w, h) = (self.canvas.w, self.canvas.h);
+	self.buffers.retain(|b| b.busy || (b.w, b.h) == (w, h));
+	if let Some(found) = self.buffers.iter().position(|b| !b.busy) {
+	    return Some(found);
+	}
+	let size = w*h*4;
+	unsafe {
+	    let fd = memfd_create(b"dmenu\0".as_ptr() as *const _, MFD_CLOEXEC);
+	    if fd < 0 {
+		return None;
+	    }
+	    let fd = OwnedFd::from_raw_fd(fd);
+	    if ftruncate(fd.as_raw_fd(), size as _) != 0 {
+		return None;
+	    }
+	    let data = mmap(ptr::null_mut(), size, PROT_READ | PROT_WRITE, MAP_SHARED, fd.as_raw_fd(), 0);
+	    if data == MAP_FAILED {
+		return None;
+	    }
+	    let pool = self.shm.create_pool(fd.as_fd(), size as i32, qh, ());
+	    self.next_buffer += 1;
+	    let buffer = pool.create_buffer(0, w as i32, h as i32, w as i32 * 4,
+					    wl_shm::Format::Argb8888, qh, self.next_buffer);
+	    self.buffers.push(Buffer{id: self.next_buffer, buffer, pool, data: data as *mut u32,
+				     w, h, busy: false, _fd: fd});
+	}
+	Some(self.buffers.len()-1)
+    }
+}
+
+impl Backend for Wayland {
+    fn load_fonts(&mut self, names: &[String]) -> CompResult<()> {
+	self.state.canvas.load_fonts(names)
+    }
+
+    fn font_height(&self) -> c_uint {
+	self.state.canvas.font_height()
+    }
+
+    fn max_font_height(&self) -> c_uint {
+	self.state.canvas.max_font_height()
+    }
+
+    fn color(&mut self, name: &str) -> Option<Color> {
+	Color::parse(name) // there's no X server to know color names
+    }
+
+    fn text_width(&mut self, text: &str) -> CompResult<c_uint> {
+	Ok(self.state.canvas.text_width(text))
+    }
+
+    fn text(&mut self, x: c_int, y: c_int, h: c_uint, text: &str, color: Color) -> CompResult<()> {
+	self.state.canvas.text(x, y, h, text, color);
+	Ok(())
+    }
+
+    fn rect(&mut self, x: c_int, y: c_int, w: c_uint, h: c_uint, color: Color, filled: bool) {
+	self.state.canvas.rect(x, y, w, h, color, filled);
+    }
+
+    fn load_image(&mut self, pixels: Vec<u32>, size: c_uint) -> usize {
+	self.state.canvas.load_image(pixels, size)
+    }
+
+    fn draw_image(&mut self, id: usize, x: c_int, y: c_int) {
+	self.state.canvas.draw_image(id, x, y);
+    }
+
+    fn grab_keyboard(&mut self, _config: &Config) -> CompResult<()> {
+	Ok(()) // the layer surface asks for exclusive keyboard focus instead
+    }
+
+    fn create_window(&mut self, h: c_int, bg: Color, config: &Config) -> CompResult<c_int> {
+	if config.embed != 0 {
+	    return Die::stderr("--embed: embedding isn't possible on Wayland".to_owned());
+	}
+	let qh = self.queue.handle();
+	let output = if config.mon >= 0 {
+	    self.state.outputs.get(config.mon as usize)
+	} else {
+	    None // the compositor picks, usually the focused one
+	};
+	let surface = self.state.compositor.create_surface(&qh, ());
+	let layer = self.state.layer_shell.get_layer_surface(&surface, output, zwlr_layer_shell_v1::Layer::Top,
+							      "dmenu".to_string(), &qh, ());
+	layer.set_size(0, h as u32);
+	layer.set_anchor(if config.topbar {Anchor::Top} else {Anchor::Bottom} | Anchor::Left | Anchor::Right);
+	layer.set_exclusive_zone(-1); // cover panels, like the X11 window does
+	layer.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+	surface.commit();
+	self.state.surface = Some((surface, layer));
+
+	while self.state.configured.is_none() {
+	    if self.state.closed {
+		return Die::stderr("the compositor closed the layer surface".to_owned());
+	    }
+	    self.queue.blocking_dispatch(&mut self.state)
+		.map_err(|err| Die::Stderr(format!("Wayland dispatch failed: {}", err)))?;
+	}
+	let (w, _) = self.state.configured.unwrap();
+	self.state.canvas.resize(w as usize, h as usize);
+	self.state.canvas.rect(0, 0, w, h as c_uint, bg, true);
+	Ok(w as c_int)
+    }
+
+    fn map(&mut self, x: c_int, y: c_int, w: c_int, h: c_int) {
+	let qh = self.queue.handle();
+	let i = match self.state.buffer(&qh) {
+	    Some(i) => i,
+	    None => return, // out of memory, try again on the next draw
+	};
+	let state = &mut self.state;
+	let buffer = &mut state.buffers[i];
+	unsafe {
+	    slice::from_raw_parts_mut(buffer.data, buffer.w*buffer.h).copy_from_slice(&state.canvas.pixels);
+	}
+	buffer.busy = true;
+	if let Some((surface, _)) = state.surface.as_ref() {
+	    surface.attach(Some(&buffer.buffer), 0, 0);
+	    surface.damage_buffer(x, y, w, h);
+	    surface.commit();
+	}
+	let _ = self.conn.flush();
+    }
+
+    fn fd(&self) -> c_int {
+	self.conn.backend().poll_fd().as_raw_fd()
+    }
+
+    fn timeout(&self) -> c_int {
+	match self.state.repeat {
+	    Some((_, next)) => next.saturating_duration_since(Instant::now()).as_millis() as c_int,
+	    None => -1,
+	}
+    }
+
+    fn pending(&mut self) -> bool {
+	if self.state.events.is_empty() && !self.state.closed {
+	    if let Err(Die::Stderr(err)) | Err(Die::Stdout(err)) = self.dispatch() {
+		eprintln!("{}", err);
+		self.state.closed = true;
+	    }
+	}
+	!self.state.events.is_empty() || self.state.closed
+    }
+
+    fn next_event(&mut self) -> CompResult<Option<Event>> {
+	loop {
+	    if let Some(event) = self.state.events.pop_front() {
+		return Ok(Some(event));
+	    }
+	    if self.state.closed {
+		return Ok(Some(Event::Close));
+	    }
+	    let mut fds = [pollfd{fd: self.fd(), events: POLLIN, revents: 0}];
+	    unsafe {
+		poll(fds.as_mut_ptr(), 1, self.timeout());
+	    }
+	    self.dispatch()?;
+	}
+    }
+
+    fn clipboard(&mut self) -> CompResult<String> {
+	let offer = match self.state.selection.as_ref() {
+	    Some(offer) => offer,
+	    None => return Ok(String::new()),
+	};
+	let mut fds: [c_int; 2] = [0; 2];
+	if unsafe{pipe(fds.as_mut_ptr())} != 0 {
+	    return Die::stderr("Could not create pipe".to_owned());
+	}
+	let (mut read, write) = unsafe{(File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))};
+	offer.receive("text/plain;charset=utf-8".to_string(), write.as_fd());
+	drop(write); // so reading ends once the source client is done
+	let _ = self.conn.flush();
+	// the keyboard is grabbed, so don't wait forever on a client that never writes
+	let deadline = Instant::now() + CLIPBOARD_TIMEOUT;
+	let mut contents = Vec::new();
+	let mut chunk = [0u8; 4096];
+	loop {
+	    let now = Instant::now();
+	    if now >= deadline {
+		return Ok(String::new());
+	    }
+	    let mut fd = pollfd{fd: read.as_raw_fd(), events: POLLIN, revents: 0};
+	    unsafe {
+		poll(&mut fd, 1, (deadline - now).as_millis() as i32 + 1);
+	    }
+	    if fd.revents == 0 {
+		continue;
+	    }
+	    match read.read(&mut chunk) {
+		Ok(0) => break,
+		Ok(n) => contents.extend_from_slice(&chunk[..n]),
+		Err(err) if err.kind() == ErrorKind::Interrupted => {},
+		Err(err) => return Die::stderr(format!("Could not read clipboard: {}", err)),
+	    }
+	}
+	Ok(String::from_utf8_lossy(&contents).into_owned())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+	self
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(_: &mut Self, _: &wl_registry::WlRegistry, _: wl_registry::Event,
+	     _: &GlobalListContents, _: &Connection, _: &QueueHandle<Self>) {
+	// outputs plugged in later can't be chosen with --monitor anyway
+    }
+}
+
+impl Dispatch<WlSeat, ()> for State {
+    fn event(_: &mut Self, seat: &WlSeat, event: wl_seat::Event,
+	     _: &(), _: &Connection, qh: &QueueHandle<Self>) {
+	if let wl_seat::Event::Capabilities{capabilities: WEnum::Value(capabilities)} = event {
+	    if capabilities.contains(wl_seat::Capability::Keyboard) {
+		seat.get_keyboard(qh, ());
+	    }
+	    if capabilities.contains(wl_seat::Capability::Pointer) {
+		seat.get_pointer(qh, ());
+	    }
+	}
+    }
+}
+
+impl Dispatch<WlKeyboard, ()> for State {
+    fn event(state: &mut Self, _: &WlKeyboard, event: wl_keyboard::Event,
+	     _: &(), _: &Connection, _: &QueueHandle<Self>) {
+	match event {
+	    wl_keyboard::Event::Keymap{format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1), fd, size} => unsafe {
+		let map = mmap(ptr::null_mut(), size as usize, PROT_READ, MAP_PRIVATE, fd.as_raw_fd(), 0);
+		if map == MAP_FAILED {
+		    return;
+		}
+		let text = slice::from_raw_parts(map as *const u8, size as usize);
+		let text = String::from_utf8_lossy(text.split(|b| *b == 0).next().unwrap_or(&[])).to_string();
+		munmap(map, size as usize);
+		if let Some(keymap) = xkb::Keymap::new_from_string(&state.xkb, text, xkb::KEYMAP_FORMAT_TEXT_V1,
+								   xkb::KEYMAP_COMPILE_NO_FLAGS) {
+		    let xkb_state = xkb::State::new(&keymap);
+		    state.keymap = Some((keymap, xkb_state));
+		}
+	    },
+	    wl_keyboard::Event::Key{key, state: WEnum::Value(key_state), ..} => {
+		let key = xkb::Keycode::new(key + 8); // evdev to xkb
+		if key_state == wl_keyboard::KeyState::Pressed {
+		    if let Some(event) = state.key_event(key) {
+			state.events.push_back(event);
+		    }
+		    let repeats = state.keymap.as_ref().map(|(keymap, _)| keymap.key_repeats(key)).unwrap_or(false);
+		    state.repeat = if repeats {
+			Some((key, Instant::now() + Duration::from_millis(state.repeat_info.1 as u64)))
+		    } else {
+			None
+		    };
+		} else if state.repeat.map(|(held, _)| held == key).unwrap_or(false) {
+		    state.repeat = None;
+		}
+	    },
+	    wl_keyboard::Event::Modifiers{mods_depressed, mods_latched, mods_locked, group, ..} => {
+		if let Some((_, xkb_state)) = state.keymap.as_mut() {
+		    xkb_state.update_mask(mods_depressed, mods_latched, mods_locked, 0, 0, group);
+		}
+	    },
+	    wl_keyboard::Event::RepeatInfo{rate, delay} => state.repeat_info = (rate, delay),
+	    wl_keyboard::Event::Leave{..} => state.repeat = None,
+	    _ => {},
+	}
+    }
+}
+
+impl Dispatch<WlPointer, ()> for State {
+    fn event(state: &mut Self, _: &WlPointer, event: wl_pointer::Event,
+	     _: &(), _: &Connection, _: &QueueHandle<Self>) {
+	let (x, y) = (state.pointer.0 as c_int, state.pointer.1 as c_int);
+	let mods = state.mods();
+	let button = match event {
+	    wl_pointer::Event::Enter{surface_x, surface_y, ..}
+	    | wl_pointer::Event::Motion{surface_x, surface_y, ..} => {
+		state.pointer = (surface_x, surface_y);
+		None
+	    },
+	    wl_pointer::Event::Button{button, state: WEnum::Value(wl_pointer::ButtonState::Pressed), ..} => {
+		match button { // linux/input-event-codes.h
+		    0x110 => Some(1), // BTN_LEFT
+		    0x112 => Some(2), // BTN_MIDDLE
+		    0x111 => Some(3), // BTN_RIGHT
+		    _ => None,
+		}
+	    },
+	    wl_pointer::Event::Axis{axis: WEnum::Value(wl_pointer::Axis::VerticalScroll), value, ..} => {
+		state.scroll += value;
+		if state.scroll.abs() < SCROLL_STEP {
+		    None
+		} else {
+		    let button = if state.scroll < 0.0 {4} else {5};
+		    state.scroll = 0.0;
+		    Some(button)
+		}
+	    },
+	    _ => None,
+	};
+	if let Some(button) = button {
+	    state.events.push_back(Event::Button{button, x, y, state: mods});
+	}
+    }
+}
+
+impl Dispatch<ZwlrLayerSurfaceV1, ()> for State {
+    fn event(state: &mut Self, layer: &ZwlrLayerSurfaceV1, event: zwlr_layer_surface_v1::Event,
+	     _: &(), _: &Connection, _: &QueueHandle<Self>) {
+	match event {
+	    zwlr_layer_surface_v1::Event::Configure{serial, width, height} => {
+		layer.ack_configure(serial);
+		if state.configured.is_none() {
+		    state.configured = Some((width, height));
+		}
+	    },
+	    zwlr_layer_surface_v1::Event::Closed => state.closed = true,
+	    _ => {},
+	}
+    }
+}
+
+impl Dispatch<WlBuffer, u32> for State {
+    fn event(state: &mut Self, _: &WlBuffer, event: wl_buffer::Event,
+	     id: &u32, _: &Connection, _: &QueueHandle<Self>) {
+	if let wl_buffer::Event::Release = event {
+	    if let Some(buffer) = state.buffers.iter_mut().find(|b| b.id == *id) {
+		buffer.busy = false;
+	    }
+	}
+    }
+}
+
+impl Dispatch<WlDataDevice, ()> for State {
+    fn event(state: &mut Self, _: &WlDataDevice, event: wl_data_device::Event,
+	     _: &(), _: &Connection, _: &QueueHandle<Self>) {
+	if let wl_data_device::Event::Selection{id} = event {
+	    if let Some(old) = state.selection.take() {
+		old.destroy();
+	    }
+	    state.selection = id;
+	}
+    }
+
+    event_created_child!(State, WlDataDevice, [
+	wl_data_device::EVT_DATA_OFFER_OPCODE => (WlDataOffer, ()),
+    ]);
+}
+
+delegate_noop!(State: WlCompositor);
+delegate_noop!(State: WlShmPool);
+delegate_noop!(State: WlDataDeviceManager);
+delegate_noop!(State: ZwlrLayerShellV1);
+delegate_noop!(State: ignore WlSurface);
+delegate_noop!(State: ignore WlShm);
+delegate_noop!(State: ignore WlOutput);
+delegate_noop!(State: ignore WlDataOffer);
@@ -0,0 +1,147 @@
+use x11::xlib::{XGetWindowProperty, XInternAtom, XSendEvent, XMapRaised, XSync, XFree,
+		XEvent, XClientMessageEvent, ClientMessageData, ClientMessage, Display, Window,
+		Atom, False, True, Success, SubstructureRedirectMask, SubstructureNotifyMask,
+		XA_CARDINAL, XA_STRING, XA_WINDOW, XA_WM_CLASS, XA_WM_NAME};
+use libc::{c_char, c_int, c_long, c_uchar, c_ulong, c_void};
+use std::{ffi::CString, mem, ptr, slice, sync::Arc};
+
+use crate::drw::Drw;
+use crate::item::Item;
+use crate::mode::Mode;
+use crate::xorg::X11;
+use crate::result::*;
+
+// _NET_WM_DESKTOP of windows shown on every desktop
+const ALL_DESKTOPS: c_ulong = 0xFFFFFFFF;
+
+/// Windows managed by an EWMH compliant window manager, see --windows
+/// Selecting one activates it, switching to its desktop first.
+#[derive(Debug, Default)]
+pub struct Windows {
+    desktops: Vec<(Window, Option<c_ulong>)>, // as listed, with the desktop they're on
+}
+
+impl Mode for Windows {
+    fn name(&self) -> &str {
+	"window"
+    }
+    fn items(&mut self, drw: &mut Drw) -> CompResult<Vec<Item>> {
+	let (dpy, root) = display(drw)?;
+	let utf8 = atom(dpy, "UTF8_STRING");
+	let clients: Vec<Window> = unsafe {
+	    property(dpy, root, atom(dpy, "_NET_CLIENT_LIST"), XA_WINDOW, 32)
+	};
+	let desktop_names: Vec<String> = string(dpy, root, atom(dpy, "_NET_DESKTOP_NAMES"), utf8)
+	    .unwrap_or_default().split('\0').map(|name| name.to_string()).collect();
+	let mut items = Vec::new();
+	self.desktops.clear();
+	for window in clients {
+	    let desktop = unsafe {
+		property::<c_ulong>(dpy, window, atom(dpy, "_NET_WM_DESKTOP"), XA_CARDINAL, 32)
+	    }.first().cloned();
+	    let title = string(dpy, window, atom(dpy, "_NET_WM_NAME"), utf8)
+		.or_else(|| string(dpy, window, XA_WM_NAME, XA_STRING))
+		.unwrap_or_default();
+	    // WM_CLASS is the instance and then the class, each null terminated
+	    let class = string(dpy, window, XA_WM_CLASS, XA_STRING).unwrap_or_default()
+		.split('\0').nth(1).unwrap_or("").to_string();
+	    let desktop_name = match desktop {
+		Some(ALL_DESKTOPS) => "*".to_string(),
+		Some(d) => desktop_names.get(d as usize).filter(|name| name.len() > 0).cloned()
+		    .unwrap_or_else(|| d.to_string()),
+		None => "-".to_string(),
+	    };
+	    let mut item = Item::new(format!("{}  {}  {}", desktop_name, class, title), false);
+	    item.payload = Some(Arc::new(format!("0x{:x}", window)));
+	    items.push(item);
+	    self.desktops.push((window, desktop.filter(|d| *d != ALL_DESKTOPS)));
+	}
+	Ok(items)
+    }
+    fn dispose(&mut self, drw: &mut Drw, output: String, recommendation: bool) -> CompResult<bool> {
+	let id = Window::from_str_radix(output.trim_start_matches("0x"), 16).ok();
+	let (window, desktop) = match self.desktops.iter().find(|(w, _)| Some(*w) == id) {
+	    Some(found) => *found,
+	    None => return Ok(false), // Shift-Return, or anything else that isn't a window
+	};
+	let (dpy, root) = display(drw)?;
+	unsafe {
+	    if let Some(desktop) = desktop {
+		send(dpy, root, root, atom(dpy, "_NET_CURRENT_DESKTOP"), [desktop as c_long, 0, 0]);
+	    }
+	    // source 2: a pager, so the request isn't refused for focus stealing
+	    send(dpy, root, window, atom(dpy, "_NET_ACTIVE_WINDOW"), [2, 0, 0]);
+	    XMapRaised(dpy, window);
+	    XSync(dpy, False);
+	}
+	Ok(recommendation)
+    }
+}
+
+// Windows are only listed on X11, other backends have no EWMH to ask
+fn display(drw: &Drw) -> CompResult<(*mut Display, Window)> {
+    match drw.backend.as_any().downcast_ref::<X11>() {
+	Some(x11) => Ok((x11.dpy, x11.root)),
+	None => Die::stderr("--windows: only available with --backend x11".to_owned()),
+    }
+}
+
+fn atom(dpy: *mut Display, name: &str) -> Atom {
+    let name = CString::new(name).unwrap();
+    unsafe {
+	XInternAtom(dpy, name.as_ptr() as *mut c_char, False)
+    }
+}
+
+// Items of a property, if it has the given type and format. T has to match the format:
+// c_uchar for 8, and c_ulong (not u32) for 32, which is how Xlib returns them
+unsafe fn property<T: Copy>(dpy: *mut Display, window: Window, property: Atom, kind: Atom,
+			    format: c_int) -> Vec<T> {
+    let mut actual_kind: Atom = 0;
+    let mut actual_format: c_int = 0;
+    let mut nitems: c_ulong = 0;
+    let mut remaining: c_ulong = 0;
+    let mut data: *mut c_uchar = ptr::null_mut();
+    if XGetWindowProperty(dpy, window, property, 0, i32::MAX as c_long, False, kind,
+			  &mut actual_kind, &mut actual_format, &mut nitems, &mut remaining,
+			  &mut data) != Success as c_int || data.is_null() {
+	return Vec::new();
+    }
+    let ret = if actual_kind == kind && actual_format == format {
+	slice::from_raw_parts(data as *const T, nitems as usize).to_vec()
+    } else {
+	Vec::new()
+    };
+    XFree(data as *mut c_void);
+    ret
+}
+
+fn string(dpy: *mut Display, window: Window, property: Atom, kind: Atom) -> Option<String> {
+    let bytes: Vec<c_uchar> = unsafe {
+	self::property(dpy, window, property, kind, 8)
+    };
+    if bytes.is_empty() {
+	None
+    } else {
+	Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+// Sends a format 32 client message about window to the window manager
+unsafe fn send(dpy: *mut Display, root: Window, window: Window, message_type: Atom, data: [c_long; 3]) {
+    let mut ev: XEvent = mem::zeroed();
+    ev.client_message = XClientMessageEvent{
+	type_: ClientMessage,
+	serial: 0,
+	send_event: True,
+	display: dpy,
+	window,
+	message_type,
+	format: 32,
+	data: ClientMessageData::new(),
+    };
+    for (i, long) in data.iter().enumerate() {
+	ev.client_message.data.set_long(i, *long);
+    }
+    XSendEvent(dpy, root, False, SubstructureRedirectMask | SubstructureNotifyMask, &mut ev);
+}
@@ -0,0 +1,560 @@
+use x11::xinerama::{XineramaQueryScreens, XineramaScreenInfo};
+use x11::xlib::{Display, Window, Drawable, GC, XIC, Atom, Pixmap, XWindowAttributes,
+		XOpenDisplay, XCloseDisplay, XSupportsLocale, XDefaultScreen, XRootWindow,
+		XGetWindowAttributes, XCreatePixmap, XFreePixmap, XCreateGC, XFreeGC,
+		XSetLineAttributes, XDefaultDepth, XDefaultColormap, XDefaultVisual,
+		JoinMiter, CapButt, LineSolid, XInternAtom, False, True,
+		XSetForeground, XFillRectangle, XDrawRectangle, XCopyArea, XSync,
+		XUngrabKey, AnyKey, AnyModifier,
+		XGetInputFocus, XSetInputFocus, PointerRoot, RevertToParent, CurrentTime,
+		XGrabKeyboard, XDefaultRootWindow, GrabSuccess, GrabModeAsync,
+		XFree, XQueryTree, XQueryPointer, XClassHint, XSetClassHint,
+		CWEventMask, CWBackPixel, CWOverrideRedirect, XCreateWindow, XSetWindowAttributes,
+		VisibilityChangeMask, KeyPressMask, ButtonPressMask, ExposureMask,
+		FocusChangeMask, SubstructureNotifyMask, XSelectInput,
+		XOpenIM, XCreateIC, XIMStatusNothing, XIMPreeditNothing, XMapRaised, XRaiseWindow,
+		XEvent, XKeyEvent, XNextEvent, XFilterEvent, XPending, XConnectionNumber,
+		XmbLookupString, XLookupChars, XLookupKeySym, XLookupBoth, KeySym,
+		DestroyNotify, Expose, FocusIn, KeyPress, ButtonPress, SelectionNotify,
+		VisibilityNotify, VisibilityUnobscured,
+		XCreateImage, XPutImage, XDestroyImage, ZPixmap};
+use x11::xft::{XftColor, XftDraw, XftDrawCreate, XftDrawDestroy, XftDrawStringUtf8,
+	       XftTextExtentsUtf8, XftCharExists, XftFontMatch, XftColorAllocName,
+	       XftColorAllocValue, XftColorFree, FcPattern};
+use x11::xrender::{XGlyphInfo, XRenderColor, XRenderCreatePicture, XRenderFindStandardFormat,
+		   XRenderFindVisualFormat, XRenderComposite, XRenderFreePicture, PictOpOver,
+		   Picture};
+use fontconfig::fontconfig::{FcPatternAddBool, FcPatternDestroy,
+			     FcCharSetCreate, FcCharSetAddChar, FcPatternDuplicate, FcPatternAddCharSet,
+			     FcCharSetDestroy, FcMatchPattern, FcConfigSubstitute};
+use clipboard::{ClipboardProvider, ClipboardContext};
+use libc::{c_char, c_int, c_uchar, c_uint, c_void, setlocale, LC_CTYPE};
+use std::{any::Any, collections::HashMap, ffi::CString, mem::MaybeUninit, ptr};
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::additional_bindings::fontconfig::{FC_SCALABLE, FC_CHARSET, FC_COLOR, FcTrue, FcFalse};
+use crate::additional_bindings::xlib::{XNFocusWindow, XNClientWindow, XNInputStyle};
+use crate::additional_bindings::xrender::PictStandardARGB32;
+use crate::backend::{Backend, Color, Event};
+use crate::config::Config;
+use crate::fnt::*;
+use crate::result::*;
+
+/// The default backend, drawing with Xft onto a pixmap that's copied to the window
+#[derive(Debug)]
+pub struct X11 {
+    pub dpy: *mut Display,
+    pub screen: c_int,
+    pub root: Window,
+    pub win: Window,
+    parentwin: Window,
+    wa: XWindowAttributes,
+    drawable: Drawable,
+    gc: GC,
+    draw: *mut XftDraw,
+    xic: XIC,
+    utf8: Atom,
+    w: c_int,
+    h: c_int,
+    fonts: Vec<Fnt>,
+    colors: HashMap<Color, *mut XftColor>, // allocated as they're drawn with
+    images: Vec<Image>,
+}
+
+/// An image uploaded to the X server, ready to be composited
+#[derive(Debug)]
+struct Image {
+    pixmap: Pixmap,
+    picture: Picture,
+    size: c_uint,
+}
+
+#[inline]
+fn intersect(x: c_int, y: c_int, w: c_int, h: c_int, r: *mut XineramaScreenInfo) -> c_int {
+    unsafe {
+	0.max((x+w).min(((*r).x_org+(*r).width) as c_int) - x.max((*r).x_org as c_int)) *
+	    0.max((y+h).min(((*r).y_org+(*r).height) as c_int) - y.max((*r).y_org as c_int))
+    }
+}
+
+impl X11 {
+    pub fn open(config: &Config) -> CompResult<Self> {
+	unsafe {
+	    if setlocale(LC_CTYPE, ptr::null())==ptr::null_mut() || XSupportsLocale()==0 {
+		return Die::stderr("warning: no locale support".to_owned());
+	    }
+	    let dpy = XOpenDisplay(ptr::null_mut());
+	    if dpy==ptr::null_mut() {
+		return Die::stderr("cannot open display".to_owned());
+	    }
+	    let screen = XDefaultScreen(dpy);
+	    let root = XRootWindow(dpy, screen);
+	    let parentwin = root.max(config.embed);
+	    let mut wa: XWindowAttributes = MaybeUninit::uninit().assume_init();
+	    XGetWindowAttributes(dpy, parentwin, &mut wa);
+
+	    let drawable = XCreatePixmap(dpy, root, wa.width as u32, wa.height as u32, XDefaultDepth(dpy, screen) as u32);
+	    let gc = XCreateGC(dpy, root, 0, ptr::null_mut());
+	    XSetLineAttributes(dpy, gc, 1, LineSolid, CapButt, JoinMiter);
+	    let draw = XftDrawCreate(dpy, drawable, XDefaultVisual(dpy, screen),
+				     XDefaultColormap(dpy, screen));
+	    let utf8 = XInternAtom(dpy, "UTF8_STRING\0".as_ptr() as *mut c_char, False);
+	    Ok(Self{dpy, screen, root, win: 0, parentwin, wa, drawable, gc, draw,
+		    xic: ptr::null_mut(), utf8, w: 0, h: 0, fonts: Vec::new(),
+		    colors: HashMap::new(), images: Vec::new()})
+	}
+    }
+
+    fn grabfocus(&self) -> CompResult<()> {
+	unsafe {
+	    let ts = Duration::from_millis(1);
+	    let mut focuswin: Window = MaybeUninit::uninit().assume_init();
+	    let mut revertwin = MaybeUninit::uninit().assume_init();
+
+	    for _ in 0..100 {
+		XGetInputFocus(self.dpy, &mut focuswin, &mut revertwin);
+		if focuswin == self.win {
+		    return Ok(());
+		}
+		XSetInputFocus(self.dpy, self.win, RevertToParent, CurrentTime);
+		sleep(ts);
+	    }
+	    Die::stderr("cannot grab focus".to_owned())
+	}
+    }
+
+    // Allocated on first use and kept until the display is closed
+    fn xft_color(&mut self, color: Color) -> *mut XftColor {
+	if let Some(xft) = self.colors.get(&color) {
+	    return *xft;
+	}
+	unsafe {
+	    let xft: *mut XftColor = Box::into_raw(Box::new(MaybeUninit::uninit().assume_init()));
+	    let value = XRenderColor{red: color.r as u16 * 257, green: color.g as u16 * 257,
+				     blue: color.b as u16 * 257, alpha: 0xffff};
+	    XftColorAllocValue(self.dpy, XDefaultVisual(self.dpy, self.screen),
+			       XDefaultColormap(self.dpy, self.screen), &value, xft);
+	    self.colors.insert(color, xft);
+	    xft
+	}
+    }
+
+    // The font to draw c with. If no font has it, a fallback is looked for.
+    fn font_for(&mut self, c: char) -> CompResult<usize> {
+	unsafe {
+	    if let Some(found) = self.fonts.iter().position(|font| XftCharExists(self.dpy, font.xfont, c as u32) == 1) {
+		return Ok(found);
+	    }
+	    // char is not found in any fonts
+	    // In this case, pretend it's in the first font, as it must be drawn
+
+	    let fccharset = FcCharSetCreate();
+	    FcCharSetAddChar(fccharset, c as u32);
+	    if self.fonts[0].pattern_pointer == ptr::null_mut() {
+		/* Refer to the comment in xfont_create for more information. */
+		return Die::stderr("fonts must be loaded from font strings".to_owned());
+	    }
+
+	    let fcpattern = FcPatternDuplicate(self.fonts[0].pattern_pointer as *const c_void);
+	    FcPatternAddCharSet(fcpattern as *mut c_void, FC_CHARSET, fccharset);
+	    FcPatternAddBool(fcpattern as *mut c_void, FC_SCALABLE, FcTrue);
+	    FcPatternAddBool(fcpattern as *mut c_void, FC_COLOR, FcFalse);
+
+	    FcConfigSubstitute(ptr::null_mut(), fcpattern as *mut c_void, FcMatchPattern);
+	    let mut result: x11::xft::FcResult = x11::xft::FcResult::NoId; // XftFontMatch isn't null safe so we need some memory (result is actually discarded)
+	    let font_match = XftFontMatch(self.dpy, self.screen, fcpattern as *const FcPattern, &mut result);
+
+	    FcCharSetDestroy(fccharset);
+	    FcPatternDestroy(fcpattern);
+
+	    if font_match != ptr::null_mut() {
+		let mut usedfont = Fnt::new(self.dpy, self.screen, None, font_match)?;
+		if XftCharExists(self.dpy, usedfont.xfont, c as u32) != 0 {
+		    self.fonts.push(usedfont);
+		    return Ok(self.fonts.len()-1);
+		}
+		usedfont.free(self.dpy);
+	    }
+	    Ok(0)
+	}
+    }
+
+    // Splits text where the font changes
+    fn runs<'a>(&mut self, text: &'a str) -> CompResult<Vec<(usize, &'a str)>> {
+	let mut runs = Vec::new();
+	let mut start = 0;
+	let mut cur_font = None;
+	for (i, c) in text.char_indices() {
+	    let font = self.font_for(c)?;
+	    if cur_font != Some(font) {
+		if let Some(cur_font) = cur_font {
+		    runs.push((cur_font, &text[start..i]));
+		}
+		cur_font = Some(font);
+		start = i;
+	    }
+	}
+	if let Some(cur_font) = cur_font {
+	    runs.push((cur_font, &text[start..]));
+	}
+	Ok(runs)
+    }
+
+    fn font_getexts(&self, font: &Fnt, text: &str) -> c_uint {
+	unsafe {
+	    let mut ext: XGlyphInfo = MaybeUninit::uninit().assume_init();
+	    XftTextExtentsUtf8(self.dpy, font.xfont, text.as_ptr() as *mut c_uchar, text.len() as c_int, &mut ext);
+	    ext.xOff as c_uint
+	}
+    }
+}
+
+#[allow(non_upper_case_globals)]
+impl Backend for X11 {
+    fn load_fonts(&mut self, names: &[String]) -> CompResult<()> {
+	for name in names {
+	    let font = Fnt::new(self.dpy, self.screen, Some(&format!("{}\0", name)), ptr::null_mut())?;
+	    self.fonts.push(font);
+	}
+	Ok(())
+    }
+
+    fn font_height(&self) -> c_uint {
+	self.fonts[0].height
+    }
+
+    fn max_font_height(&self) -> c_uint {
+	self.fonts.iter().map(|f| f.height).max().unwrap_or(0)
+    }
+
+    fn color(&mut self, name: &str) -> Option<Color> {
+	let clrname = CString::new(name).ok()?;
+	unsafe {
+	    let (visual, cmap) = (XDefaultVisual(self.dpy, self.screen), XDefaultColormap(self.dpy, self.screen));
+	    let mut xft: XftColor = MaybeUninit::uninit().assume_init();
+	    if XftColorAllocName(self.dpy, visual, cmap, clrname.as_ptr(), &mut xft) == 0 {
+		return None;
+	    }
+	    XftColorFree(self.dpy, visual, cmap, &mut xft);
+	    Some(Color{r: (xft.color.red >> 8) as u8, g: (xft.color.green >> 8) as u8,
+		       b: (xft.color.blue >> 8) as u8})
+	}
+    }
+
+    fn text_width(&mut self, text: &str) -> CompResult<c_uint> {
+	Ok(self.runs(text)?.into_iter()
+	   .map(|(font, run)| self.font_getexts(&self.fonts[font], run))
+	   .sum())
+    }
+
+    fn text(&mut self, mut x: c_int, y: c_int, h: c_uint, text: &str, color: Color) -> CompResult<()> {
+	let xft = self.xft_color(color);
+	for (font, run) in self.runs(text)? {
+	    let usedfont = &self.fonts[font];
+	    unsafe {
+		let ty = y + (h as i32 - usedfont.height as i32) / 2 + (*usedfont.xfont).ascent;
+		XftDrawStringUtf8(self.draw, xft, usedfont.xfont, x, ty, run.as_ptr() as *mut c_uchar, run.len() as c_int);
+	    }
+	    x += self.font_getexts(usedfont, run) as c_int;
+	}
+	Ok(())
+    }
+
+    fn rect(&mut self, x: c_int, y: c_int, w: c_uint, h: c_uint, color: Color, filled: bool) {
+	let xft = self.xft_color(color);
+	unsafe {
+	    XSetForeground(self.dpy, self.gc, (*xft).pixel);
+	    if filled {
+		XFillRectangle(self.dpy, self.drawable, self.gc, x, y, w, h);
+	    } else {
+		XDrawRectangle(self.dpy, self.drawable, self.gc, x, y, w - 1, h - 1);
+	    }
+	}
+    }
+
+    fn load_image(&mut self, mut pixels: Vec<u32>, size: c_uint) -> usize {
+	unsafe {
+	    let pixmap = XCreatePixmap(self.dpy, self.drawable, size, size, 32);
+	    let gc = XCreateGC(self.dpy, pixmap, 0, ptr::null_mut());
+	    let image = XCreateImage(self.dpy, XDefaultVisual(self.dpy, self.screen), 32, ZPixmap, 0,
+				     pixels.as_mut_ptr() as *mut c_char, size, size, 32, 0);
+	    XPutImage(self.dpy, pixmap, gc, image, 0, 0, 0, 0, size, size);
+	    (*image).data = ptr::null_mut(); // owned by pixels, don't let X free it
+	    XDestroyImage(image);
+	    XFreeGC(self.dpy, gc);
+	    let picture = XRenderCreatePicture(self.dpy, pixmap,
+					       XRenderFindStandardFormat(self.dpy, PictStandardARGB32),
+					       0, ptr::null());
+	    self.images.push(Image{pixmap, picture, size});
+	    self.images.len()-1
+	}
+    }
+
+    fn draw_image(&mut self, id: usize, x: c_int, y: c_int) {
+	let image = &self.images[id];
+	unsafe {
+	    let dest = XRenderCreatePicture(self.dpy, self.drawable,
+					    XRenderFindVisualFormat(self.dpy, XDefaultVisual(self.dpy, self.screen)),
+					    0, ptr::null());
+	    XRenderComposite(self.dpy, PictOpOver, image.picture, 0, dest,
+			     0, 0, 0, 0, x, y, image.size, image.size);
+	    XRenderFreePicture(self.dpy, dest);
+	}
+    }
+
+    fn grab_keyboard(&mut self, config: &Config) -> CompResult<()> {
+	let ts = Duration::from_millis(1);
+
+	if config.embed != 0 {
+	    return Ok(());
+	}
+	/* try to grab keyboard, we may have to wait for another process to ungrab */
+	for _ in 0..1000 {
+	    if unsafe{XGrabKeyboard(self.dpy, XDefaultRootWindow(self.dpy), True, GrabModeAsync,
+				    GrabModeAsync, CurrentTime) == GrabSuccess} {
+		return Ok(());
+	    }
+	    sleep(ts);
+	}
+	Die::stderr("cannot grab keyboard".to_owned())
+    }
+
+    fn create_window(&mut self, h: c_int, bg: Color, config: &Config) -> CompResult<c_int> {
+	let (parentwin, root) = (self.parentwin, self.root);
+	self.h = h;
+	unsafe {
+	    let mut x: c_int = MaybeUninit::uninit().assume_init();
+	    let mut y: c_int = MaybeUninit::uninit().assume_init();
+
+	    let mut ch: XClassHint = XClassHint{
+		res_name: (*b"dmenu\0").as_ptr() as *mut c_char,
+		res_class: (*b"dmenu\0").as_ptr() as *mut c_char
+	    };
+
+	    let mut dws: *mut Window = MaybeUninit::uninit().assume_init();
+	    let mut w:  Window = MaybeUninit::uninit().assume_init();
+	    let mut dw: Window = MaybeUninit::uninit().assume_init();
+	    let mut du: c_uint = MaybeUninit::uninit().assume_init();
+	    let mut n:  c_int  = MaybeUninit::uninit().assume_init();
+	    let info = if cfg!(feature = "Xinerama") && parentwin == root {
+		XineramaQueryScreens(self.dpy, &mut n)
+	    } else {
+		ptr::null_mut()
+	    };
+	    if cfg!(feature = "Xinerama") && info != ptr::null_mut() {
+		let mut i = 0;
+		let mut area = 0;
+		let mut di: c_int  = MaybeUninit::uninit().assume_init();
+		let mut a;
+		let mut pw;
+
+		XGetInputFocus(self.dpy, &mut w, &mut di);
+		if config.mon >= 0 && config.mon < n {
+		    i = config.mon;
+		} else if w != root && w != PointerRoot as u64 && w != 0 {
+		    /* find top-level window containing current input focus */
+		    while {
+			pw = w;
+			if XQueryTree(self.dpy, pw, &mut dw, &mut w, &mut dws, &mut du) != 0 && dws != ptr::null_mut() {
+			    XFree(dws as *mut c_void);
+			}
+			w != root && w != pw
+		    } {} // do-while
+		    /* find xinerama screen with which the window intersects most */
+		    if XGetWindowAttributes(self.dpy, pw, &mut self.wa) != 0 {
+			for j in 0..n {
+			    a = intersect(self.wa.x, self.wa.y, self.wa.width, self.wa.height, info.offset(j as isize));
+			    if a > area {
+				area = a;
+				i = j;
+			    }
+			}
+		    }
+		}
+		/* no focused window is on screen, so use pointer location instead */
+		if config.mon < 0 && area == 0 && XQueryPointer(self.dpy, root, &mut dw, &mut dw, &mut x, &mut y, &mut di, &mut di, &mut du) != 0 {
+		    for j in 0..n {
+			i = j; // this is here to bypass rust's shadowing rules in an efficient way
+			if intersect(x, y, 1, 1, info.offset(i as isize)) != 0 {
+			    break;
+			}
+		    }
+		}
+		x = (*info.offset(i as isize)).x_org as c_int;
+		y = (*info.offset(i as isize)).y_org as c_int + (if config.topbar {0} else {(*info.offset(i as isize)).height as c_int - h});
+		self.w = (*info.offset(i as isize)).width as c_int;
+		XFree(info as *mut c_void);
+	    } else {
+		if XGetWindowAttributes(self.dpy, parentwin, &mut self.wa) == 0 {
+		    return Die::stderr(format!("could not get embedding window attributes: 0x{:?}", parentwin));
+		}
+		x = 0;
+		y = if config.topbar {
+		    0
+		} else {
+		    self.wa.height - h
+		};
+		self.w = self.wa.width;
+	    }
+
+	    let mut swa: XSetWindowAttributes = MaybeUninit::uninit().assume_init();
+	    swa.override_redirect = true as i32;
+	    swa.background_pixel = (*self.xft_color(bg)).pixel;
+	    swa.event_mask = ExposureMask | KeyPressMask | VisibilityChangeMask | ButtonPressMask;
+	    self.win =
+		XCreateWindow(self.dpy, parentwin, x, y, self.w as u32,
+			      h as u32, 0, 0,
+			      0, ptr::null_mut(),
+			      CWOverrideRedirect | CWBackPixel | CWEventMask, &mut swa);
+	    XSetClassHint(self.dpy, self.win, &mut ch);
+
+	    /* input methods */
+	    let xim = XOpenIM(self.dpy, ptr::null_mut(), ptr::null_mut(), ptr::null_mut());
+	    if xim == ptr::null_mut() {
+		return Die::stderr("XOpenIM failed: could not open input device".to_owned());
+	    }
+
+
+	    self.xic = XCreateIC(xim, XNInputStyle,
+				 XIMPreeditNothing | XIMStatusNothing,
+				 XNClientWindow, self.win,
+				 XNFocusWindow, self.win,
+				 ptr::null_mut::<c_void>());
+	    // void* makes sure the value is large enough for varargs to properly stop
+	    // parsing. Any smaller and it will skip over, causing a segfault
+
+
+	    XMapRaised(self.dpy, self.win);
+
+	    if config.embed != 0 {
+		XSelectInput(self.dpy, parentwin, FocusChangeMask | SubstructureNotifyMask);
+		if XQueryTree(self.dpy, parentwin, &mut dw, &mut w, &mut dws, &mut du) != 0 && dws != ptr::null_mut() {
+		    for i in 0..du {
+			if *dws.offset(i as isize) == self.win {
+			    break;
+			}
+			XSelectInput(self.dpy, *dws.offset(i as isize), FocusChangeMask);
+		    }
+		    XFree(dws as *mut c_void);
+		}
+		self.grabfocus()?;
+	    }
+	}
+	Ok(self.w)
+    }
+
+    fn map(&mut self, x: c_int, y: c_int, w: c_int, h: c_int) {
+	unsafe {
+	    XCopyArea(self.dpy, self.drawable, self.win, self.gc, x, y, w as u32, h as u32, x, y);
+	    XSync(self.dpy, False);
+	}
+    }
+
+    fn fd(&self) -> c_int {
+	unsafe{XConnectionNumber(self.dpy)}
+    }
+
+    fn pending(&mut self) -> bool {
+	unsafe{XPending(self.dpy) != 0}
+    }
+
+    fn next_event(&mut self) -> CompResult<Option<Event>> {
+	unsafe {
+	    let mut ev: XEvent = MaybeUninit::uninit().assume_init();
+	    if XNextEvent(self.dpy, &mut ev) != 0 {
+		return Ok(Some(Event::Close));
+	    }
+	    if XFilterEvent(&mut ev, self.win) != 0 {
+		return Ok(None);
+	    }
+	    Ok(match ev.type_ {
+		DestroyNotify => if ev.destroy_window.window != self.win {
+		    Some(Event::Close)
+		} else {
+		    None
+		},
+		Expose => {
+		    if ev.expose.count == 0 {
+			self.map(0, 0, self.w, self.h);
+		    }
+		    None
+		},
+		FocusIn => {
+		    /* regrab focus from parent window */
+		    self.grabfocus()?;
+		    None
+		},
+		KeyPress => lookup(self.xic, ev.key),
+		ButtonPress => Some(Event::Button{button: ev.button.button, x: ev.button.x,
+						  y: ev.button.y, state: ev.button.state}),
+		SelectionNotify => if ev.selection.property == self.utf8 {
+		    Some(Event::Paste)
+		} else {
+		    None
+		},
+		VisibilityNotify => {
+		    if ev.visibility.state != VisibilityUnobscured {
+			XRaiseWindow(self.dpy, self.win);
+		    }
+		    None
+		},
+		_ => None,
+	    })
+	}
+    }
+
+    fn clipboard(&mut self) -> CompResult<String> {
+	let mut ctx: ClipboardContext = match ClipboardProvider::new() {
+	    Ok(ctx) => ctx,
+	    Err(_) => return Die::stderr("Could not grab clipboard".to_owned()),
+	};
+	ctx.get_contents().map_err(|err| Die::Stderr(err.to_string()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+	self
+    }
+}
+
+// Turns a key press into a keysym and the text it types
+#[allow(non_upper_case_globals)]
+fn lookup(xic: XIC, mut ev: XKeyEvent) -> Option<Event> {
+    unsafe {
+	let mut buf: [u8; 32] = [0; 32];
+	let mut ksym: KeySym = MaybeUninit::uninit().assume_init();
+	let mut status = MaybeUninit::uninit().assume_init();
+	let len = XmbLookupString(xic, &mut ev, buf.as_mut_ptr() as *mut i8, buf.len() as i32, &mut ksym, &mut status);
+	let text = String::from_utf8_lossy(&buf[..len.max(0) as usize]).to_string();
+	match status {
+	    XLookupChars => Some(Event::Key{ksym: 0, text, state: ev.state}), // NoSymbol, just text
+	    XLookupKeySym | XLookupBoth => Some(Event::Key{ksym: ksym as u32, text, state: ev.state}),
+	    _ => None, /* XLookupNone, XBufferOverflow */
+	}
+    }
+}
+
+impl Drop for X11 {
+    fn drop(&mut self) {
+	unsafe {
+	    for font in &mut self.fonts {
+		font.free(self.dpy);
+	    }
+	    XUngrabKey(self.dpy, AnyKey, AnyModifier, self.root);
+	    for xft in self.colors.values() {
+		XftColorFree(self.dpy, XDefaultVisual(self.dpy, self.screen),
+			     XDefaultColormap(self.dpy, self.screen), *xft);
+		drop(Box::from_raw(*xft));
+	    }
+	    for image in &self.images {
+		XRenderFreePicture(self.dpy, image.picture);
+		XFreePixmap(self.dpy, image.pixmap);
+	    }
+	    XftDrawDestroy(self.draw);
+	    XFreePixmap(self.dpy, self.drawable);
+	    XFreeGC(self.dpy, self.gc);
+	    XSync(self.dpy, False);
+	    XCloseDisplay(self.dpy);
+	}
+    }
+}
@@ -8,7 +8,7 @@ use crate::result::*;
 impl Drw {
     pub fn postprocess_matches(&mut self, mut current_matches: Vec<Item>) -> CompResult<Vec<Item>> {
 	if current_matches.len() == 1 {
-	    self.dispose(current_matches.swap_remove(0).text, true)?;
+	    self.dispose(current_matches.swap_remove(0).output().clone(), true)?;
 	    Err(Die::Stdout("".to_owned()))
 	} else {
 	    Ok(current_matches)
@@ -30,7 +30,7 @@ impl Drw {
     pub fn gen_matches(&mut self) -> CompResult<Vec<Item>> {
 	let eval = self.config.prompt.clone() + " " + &self.input;
 	if let Ok(evaluated) = block_on(timed_eval(eval)) {
-	    Ok(vec![Item::new(evaluated, false, self)?])
+	    Ok(vec![Item::new(evaluated, false)])
 	} else {
 	    Ok(vec![])
 	}
@@ -26,11 +26,11 @@ impl Drw {
 		match checker.check(&self.input) {
 		    Ok(mut res) => {
 			if res.is_empty() {
-			    Ok(vec![Item::new(self.input.clone(), false, self)?])
+			    Ok(vec![Item::new(self.input.clone(), false)])
 			} else {
 			    let mut ret = Vec::new();
 			    for word in res.swap_remove(0).suggestions.into_iter() {
-				ret.push(Item::new(word, false, self)?);
+				ret.push(Item::new(word, false));
 			    }
 			    Ok(ret)
 			}
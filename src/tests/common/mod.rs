@@ -0,0 +1,38 @@
+// Runs the dmenu binary headless, pressing keys as with --replay
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output, Stdio};
+
+/// An empty directory for a test to write into, kept afterwards for a look at what failed
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("dmenu-tests-{}", process::id())).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Runs dmenu with args and items on stdin, pressing keys (see $DMENU_KEYS) and drawing
+/// the menu to png. The user's config file is left out.
+/// Returns what was printed and the exit code.
+pub fn dmenu(args: &[&str], keys: &str, items: &str, png: &Path) -> (String, i32) {
+    let output = dmenu_output(args, keys, items, png);
+    (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code().unwrap_or(-1))
+}
+
+/// Like dmenu, but with stderr as well
+pub fn dmenu_output(args: &[&str], keys: &str, items: &str, png: &Path) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dmenu"))
+	.args(args)
+	.arg("--render-to").arg(png)
+	.env("DMENU_KEYS", keys)
+	.env("XDG_CONFIG_HOME", png.parent().unwrap())
+	.stdin(Stdio::piped())
+	.stdout(Stdio::piped())
+	.stderr(Stdio::piped())
+	.spawn().expect("could not run dmenu");
+    child.stdin.take().unwrap().write_all(items.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
@@ -0,0 +1,39 @@
+//! A runtime plugin for tests/plugin.rs, built with rustc as it runs.
+//! Passing its --shout flag prints selections upper case.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SHOUT: AtomicBool = AtomicBool::new(false);
+
+// A string field of a request, good enough for the ones dmenu sends here
+fn field<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    let start = request.find(&format!("\"{}\":\"", name))? + name.len() + 4;
+    request[start..].find('"').map(|end| &request[start..start+end])
+}
+
+#[no_mangle]
+pub extern "C" fn dmenu_plugin_call(request: *const c_char) -> *mut c_char {
+    let request = unsafe{CStr::from_ptr(request)}.to_string_lossy();
+    let response = match field(&request, "hook") {
+	Some("describe") => r#"{"args": [{"name": "shout", "help": "Print selections upper case"}],
+				  "hooks": ["dispose"]}"#,
+	Some("init") => {
+	    SHOUT.store(request.contains("\"shout\":true"), Ordering::Relaxed);
+	    "null"
+	},
+	Some("dispose") if SHOUT.load(Ordering::Relaxed) => {
+	    println!("{}", field(&request, "output").unwrap_or("").to_uppercase());
+	    r#"{"exit": true}"#
+	},
+	Some("init") | Some("dispose") => "null",
+	_ => "{}", // invalid, so sending a hook that wasn't listed fails the test
+    };
+    CString::new(response).unwrap().into_raw()
+}
+
+#[no_mangle]
+pub extern "C" fn dmenu_plugin_free(response: *mut c_char) {
+    unsafe{drop(CString::from_raw(response))};
+}
@@ -0,0 +1,120 @@
+//! Presses keys in a headless menu with $DMENU_KEYS, and checks what dmenu printed
+//! and how it exited. Run with `make check`.
+
+mod common;
+
+const ITEMS: &str = "alpha\nbravo\ncharlie\ndelta\necho\nfoxtrot\ngolf\nhotel\n";
+
+// Runs dmenu on ITEMS after pressing keys, returning what was printed and the exit code
+fn press(name: &str, args: &[&str], keys: &str) -> (String, i32) {
+    common::dmenu(args, keys, ITEMS, &common::scratch(name).join("menu.png"))
+}
+
+#[test]
+fn return_prints_selection() {
+    assert_eq!(press("return_prints_selection", &[], "\"al\" Return"), ("alpha\n".to_owned(), 0));
+}
+
+#[test]
+fn escape_fails() {
+    assert_eq!(press("escape_fails", &[], "\"al\" Escape"), ("".to_owned(), 1));
+}
+
+#[test]
+fn tab_completes() {
+    assert_eq!(press("tab_completes", &[], "\"br\" Tab S-Return"), ("bravo\n".to_owned(), 0));
+}
+
+#[test]
+fn ctrl_w_deletes_word() {
+    assert_eq!(press("ctrl_w_deletes_word", &[], "\"foo bar\" C-w \"baz\" S-Return"),
+	       ("foo baz\n".to_owned(), 0));
+}
+
+#[test]
+fn ctrl_k_deletes_to_end() {
+    assert_eq!(press("ctrl_k_deletes_to_end", &[], "\"foo bar\" Left Left Left C-k S-Return"),
+	       ("foo \n".to_owned(), 0));
+}
+
+#[test]
+fn paging() {
+    assert_eq!(press("paging", &["-l", "3"], "Next Return"), ("delta\n".to_owned(), 0));
+    assert_eq!(press("paging", &["-l", "3"], "M-j M-j Return"), ("golf\n".to_owned(), 0));
+    assert_eq!(press("paging", &["-l", "3"], "Next Next Prior Return"), ("delta\n".to_owned(), 0));
+}
+
+#[test]
+fn shift_types_upper_case() {
+    assert_eq!(press("shift_types_upper_case", &[], "S-a \"l\" S-Return"), ("Al\n".to_owned(), 0));
+}
+
+#[test]
+fn shift_return_prints_input() {
+    assert_eq!(press("shift_return_prints_input", &[], "\"al\" S-Return"), ("al\n".to_owned(), 0));
+}
+
+#[test]
+fn max_width_without_items() {
+    assert_eq!(press("max_width_without_items", &["--nostdin", "--render_default_width", "max"],
+		     "\"al\" Return"), ("al\n".to_owned(), 0));
+}
+
+#[test]
+fn bind_rebinds() {
+    assert_eq!(press("bind_rebinds", &["--bind", "Tab:down"], "Tab Return"), ("bravo\n".to_owned(), 0));
+    assert_eq!(press("bind_rebinds", &["--bind", "M-x:last"], "M-x Return"), ("hotel\n".to_owned(), 0));
+    assert_eq!(press("bind_rebinds", &["--bind", "S-Return:accept"], "\"al\" S-Return"),
+	       ("alpha\n".to_owned(), 0));
+    assert_eq!(press("bind_rebinds", &["--bind", "Tab:none"], "\"br\" Tab S-Return"),
+	       ("br\n".to_owned(), 0));
+}
+
+#[test]
+fn bind_rejects_invalid() {
+    for (binding, error) in &[("Tab", "--bind: expected KEY:ACTION, got 'Tab'"),
+			      ("X-a:down", "--bind: unknown modifier in 'X-a'"),
+			      ("Nokey:down", "--bind: unknown key 'Nokey'"),
+			      ("C-q:launch", "--bind: unknown action 'launch'")] {
+	let png = common::scratch("bind_rejects_invalid").join("menu.png");
+	let output = common::dmenu_output(&["--bind", binding], "Return", ITEMS, &png);
+	assert_eq!(output.status.code(), Some(1), "{}", binding);
+	assert_eq!(String::from_utf8_lossy(&output.stderr).trim_end(), *error);
+	assert!(output.stdout.is_empty(), "{}", binding);
+    }
+}
+
+// With --vi, bare keys after Escape are normal mode commands, while quoted text is typed
+fn vi(name: &str, keys: &str) -> (String, i32) {
+    press(name, &["--vi"], keys)
+}
+
+#[test]
+fn vi_escape_enters_normal_mode() {
+    // the cursor steps back onto the last char, and Escape again exits
+    assert_eq!(vi("vi_escape_enters_normal_mode", "\"foo\" Escape x S-Return"), ("fo\n".to_owned(), 0));
+    assert_eq!(vi("vi_escape_enters_normal_mode", "\"foo\" Escape Escape"), ("".to_owned(), 1));
+}
+
+#[test]
+fn vi_motions() {
+    assert_eq!(vi("vi_motions", "\"abc\" Escape h h x S-Return"), ("bc\n".to_owned(), 0));
+    assert_eq!(vi("vi_motions", "\"abc\" Escape 0 l x S-Return"), ("ac\n".to_owned(), 0));
+    assert_eq!(vi("vi_motions", "\"foo bar baz\" Escape 0 w x S-Return"), ("foo ar baz\n".to_owned(), 0));
+    assert_eq!(vi("vi_motions", "\"foo bar baz\" Escape b b x S-Return"), ("foo ar baz\n".to_owned(), 0));
+}
+
+#[test]
+fn vi_delete() {
+    assert_eq!(vi("vi_delete", "\"foo bar\" Escape x x S-Return"), ("foo b\n".to_owned(), 0));
+    assert_eq!(vi("vi_delete", "\"foo bar baz\" Escape 0 d w S-Return"), ("bar baz\n".to_owned(), 0));
+    assert_eq!(vi("vi_delete", "\"foo bar\" Escape d d i \"new\" S-Return"), ("new\n".to_owned(), 0));
+}
+
+#[test]
+fn vi_back_to_insert() {
+    // in insert mode bare keys type again
+    assert_eq!(vi("vi_back_to_insert", "\"foo\" Escape h i x S-Return"), ("fxoo\n".to_owned(), 0));
+    assert_eq!(vi("vi_back_to_insert", "\"foo\" Escape h a x S-Return"), ("foxo\n".to_owned(), 0));
+    assert_eq!(vi("vi_back_to_insert", "\"foo\" Escape 0 c w \"bar\" S-Return"), ("bar\n".to_owned(), 0));
+}
@@ -0,0 +1,34 @@
+//! Runtime plugins, built from fixtures/ as the tests run.
+//! A compiled in plugin overriding ConfigDefault with a flag reads CLAP_FLAGS when the
+//! config is set up, which must not happen before runtime plugins add their flags.
+//! `make check` runs these again with PLUGINS=maxlength to cover that.
+
+mod common;
+
+use std::{env, path::{Path, PathBuf}, process::Command};
+
+const ITEMS: &str = "alpha\nbravo\ncharlie\n";
+
+// Builds fixtures/NAME.rs into dir, returning the path of the library
+fn build(name: &str, dir: &Path) -> PathBuf {
+    let src: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests", "fixtures", &format!("{}.rs", name)]
+	.iter().collect();
+    let lib = dir.join(format!("lib{}.so", name));
+    let status = Command::new(env::var_os("RUSTC").unwrap_or_else(|| "rustc".into()))
+	.args(&["--crate-type", "cdylib", "--edition", "2018", "-o"]).arg(&lib).arg(&src)
+	.status().expect("could not run rustc");
+    assert!(status.success(), "could not build {}", src.display());
+    lib
+}
+
+#[test]
+fn runtime_plugin_flag() {
+    let dir = common::scratch("runtime_plugin_flag");
+    let lib = build("shout", &dir);
+    let lib = lib.to_str().unwrap();
+    let png = dir.join("menu.png");
+    assert_eq!(common::dmenu(&["--plugin", lib, "--shout"], "\"al\" Return", ITEMS, &png),
+	       ("ALPHA\n".to_owned(), 0));
+    assert_eq!(common::dmenu(&[&format!("--plugin={}", lib)], "\"al\" Return", ITEMS, &png),
+	       ("alpha\n".to_owned(), 0));
+}
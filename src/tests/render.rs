@@ -0,0 +1,81 @@
+//! Snapshots of the menu's layout, drawn with --render-to
+//! Run with `make check`. After changing how the menu looks, redraw them by running it
+//! with DMENU_UPDATE_SNAPSHOTS=1 and look over the new images before committing them.
+//! They are drawn with DejaVu Sans Mono, so won't match if fontconfig can't find it.
+
+mod common;
+
+use png::Decoder;
+use std::{env, fs::{self, File}, path::{Path, PathBuf}};
+
+const ITEMS: &str = "alpha\nbravo\ncharlie\ndelta\necho\nfoxtrot\ngolf\nhotel\nindia\njuliett\n\
+		     kilo\nlima\nmike\nnovember\noscar\npapa\nquebec\nromeo\nsierra\ntango\n\
+		     uniform\nvictor\nwhiskey\nx-ray\nyankee\nzulu\n";
+
+// Every token is o, so items still match while the input is too wide for flex to
+// make room for, and overrun has to draw over them
+const LONG_INPUT: &str = "\"o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o o\"";
+
+// Width, height and RGBA pixels
+fn decode(path: &Path) -> (u32, u32, Vec<u8>) {
+    let file = File::open(path).unwrap_or_else(|err| panic!("cannot open {}: {}", path.display(), err));
+    let (info, mut reader) = Decoder::new(file).read_info().unwrap();
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    (info.width, info.height, pixels)
+}
+
+// Draws the menu with args after pressing keys, and compares it with snapshots/NAME.png
+fn snapshot(name: &str, args: &[&str], keys: &str) {
+    let png = common::scratch(name).join("menu.png");
+    let mut all = vec!["--font", "DejaVu Sans Mono:size=10", "--prompt", "run"];
+    all.extend_from_slice(args);
+    let (_, code) = common::dmenu(&all, keys, ITEMS, &png);
+    assert_eq!(code, 0, "dmenu failed drawing {}", name);
+
+    let expected: PathBuf = [env!("CARGO_MANIFEST_DIR"), "..", "tests", "snapshots", &format!("{}.png", name)]
+	.iter().collect();
+    if env::var_os("DMENU_UPDATE_SNAPSHOTS").is_some() {
+	fs::copy(&png, &expected).unwrap();
+	return;
+    }
+    assert!(expected.exists(), "no snapshot {}, run with DMENU_UPDATE_SNAPSHOTS=1 to draw it",
+	    expected.display());
+    assert!(decode(&png) == decode(&expected), "{} doesn't look like {}",
+	    png.display(), expected.display());
+}
+
+#[test]
+fn default_width_min() {
+    snapshot("default_width_min", &["--render_default_width", "min"], "\"o\"");
+}
+
+#[test]
+fn default_width_items() {
+    snapshot("default_width_items", &["--render_default_width", "items"], "");
+}
+
+#[test]
+fn default_width_max() {
+    snapshot("default_width_max", &["--render_default_width", "max"], "Right Right");
+}
+
+#[test]
+fn default_width_custom() {
+    snapshot("default_width_custom", &["--render_default_width", "custom=40"], "");
+}
+
+#[test]
+fn flex() {
+    snapshot("flex", &["--render_flex"], LONG_INPUT);
+}
+
+#[test]
+fn overrun() {
+    snapshot("overrun", &["--render_overrun"], LONG_INPUT);
+}
+
+#[test]
+fn rightalign() {
+    snapshot("rightalign", &["--render_rightalign"], "\"o\"");
+}